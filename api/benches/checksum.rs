@@ -0,0 +1,47 @@
+// Compares hashing several large sources serially against hashing them in parallel.
+//
+// Run with `cargo bench -p api --bench checksum`.
+use api::checksum::{sha256_file, sha256_files};
+use std::{fs::File, io::Write, path::PathBuf, time::Instant};
+
+const FILES: usize = 8;
+const FILE_SIZE: usize = 64 * 1024 * 1024;
+
+fn main() {
+    let dir = tempfile::tempdir().expect("Couldn't create temporary directory");
+    let chunk = vec![0xa5u8; 1024 * 1024];
+
+    let paths = (0..FILES)
+        .map(|i| {
+            let path = dir.path().join(format!("source-{}.tar", i));
+            let mut file = File::create(&path).expect("Couldn't create source");
+
+            for _ in 0..FILE_SIZE / chunk.len() {
+                file.write_all(&chunk).expect("Couldn't write source");
+            }
+
+            path
+        })
+        .collect::<Vec<PathBuf>>();
+
+    let start = Instant::now();
+    for path in &paths {
+        sha256_file(path).expect("Couldn't hash source");
+    }
+    let serial = start.elapsed();
+
+    let start = Instant::now();
+    for result in sha256_files(&paths) {
+        result.expect("Couldn't hash source");
+    }
+    let parallel = start.elapsed();
+
+    println!(
+        "{} files of {} MiB: serial {:?}, parallel {:?} ({:.2}x)",
+        FILES,
+        FILE_SIZE / 1024 / 1024,
+        serial,
+        parallel,
+        serial.as_secs_f64() / parallel.as_secs_f64()
+    );
+}
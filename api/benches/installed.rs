@@ -0,0 +1,44 @@
+// Compares scanning the install database for every package checked, like `is_installed` used to,
+// against scanning it once into an index.
+//
+// Run with `cargo bench -p api --bench installed`.
+use api::package::InstalledIndex;
+use std::{fs, time::Instant};
+
+const PACKAGES: usize = 500;
+
+fn main() {
+    let db = tempfile::tempdir().expect("Couldn't create temporary directory");
+
+    let names = (0..PACKAGES)
+        .map(|i| format!("package-{}", i))
+        .collect::<Vec<String>>();
+
+    for name in &names {
+        let dir = db.path().join(name);
+
+        fs::create_dir_all(&dir).expect("Couldn't create package");
+        fs::write(dir.join("installed"), "").expect("Couldn't create package");
+    }
+
+    let start = Instant::now();
+    for name in &names {
+        assert!(InstalledIndex::scan(db.path()).is_installed(name));
+    }
+    let scanning = start.elapsed();
+
+    let start = Instant::now();
+    let index = InstalledIndex::scan(db.path());
+    for name in &names {
+        assert!(index.is_installed(name));
+    }
+    let indexed = start.elapsed();
+
+    println!(
+        "checking {} installed packages: scanning {:?}, indexed {:?} ({:.2}x)",
+        PACKAGES,
+        scanning,
+        indexed,
+        scanning.as_secs_f64() / indexed.as_secs_f64()
+    );
+}
@@ -0,0 +1,62 @@
+// Compares loading the compressed manifest of a package with tens of thousands of files
+// against reading an uncompressed one, like packages installed before compression have,
+// and against only decompressing it by running gzip, like loading it used to.
+//
+// Run with `cargo bench -p api --bench manifest`.
+use api::manifest::Manifest;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::Instant,
+};
+
+const ENTRIES: usize = 50_000;
+const RUNS: u32 = 20;
+
+fn main() {
+    let dir = tempfile::tempdir().expect("Couldn't create temporary directory");
+
+    let paths = (0..ENTRIES)
+        .map(|n| PathBuf::from(format!("/usr/share/data/{}/file{}", n % 100, n)))
+        .collect::<Vec<PathBuf>>();
+    let manifest = Manifest::from_paths(Path::new("/"), &paths);
+
+    manifest
+        .write(&dir.path().join("manifest"))
+        .expect("Couldn't write manifest");
+
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        assert_eq!(Manifest::load(dir.path()).unwrap().entries().len(), ENTRIES);
+    }
+    let uncompressed = start.elapsed() / RUNS;
+
+    manifest.store(dir.path()).expect("Couldn't store manifest");
+
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        assert_eq!(Manifest::load(dir.path()).unwrap().entries().len(), ENTRIES);
+    }
+    let compressed = start.elapsed() / RUNS;
+
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        let output = Command::new("gzip")
+            .arg("-dc")
+            .arg(dir.path().join("manifest.gz"))
+            .output()
+            .expect("Couldn't run gzip");
+
+        assert!(output.status.success());
+    }
+    let spawned = start.elapsed() / RUNS;
+
+    println!(
+        "loading a manifest of {} entries: uncompressed {:?}, compressed {:?}, gzip {:?} ({:.2}x)",
+        ENTRIES,
+        uncompressed,
+        compressed,
+        spawned,
+        spawned.as_secs_f64() / compressed.as_secs_f64()
+    );
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// The state of every file within a set of directories at some point in time,
+/// used to find out which files an install script touched outside of its build directory.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    files: HashMap<PathBuf, (Option<SystemTime>, u64)>,
+}
+
+impl Snapshot {
+    /// Records every file within the given directories, except for those within an excluded one.
+    ///
+    /// Symlinks are recorded rather than followed, so this can't loop.
+    pub fn take(dirs: &[PathBuf], exclude: &[PathBuf]) -> Self {
+        let mut snapshot = Self::default();
+
+        for dir in dirs {
+            snapshot.record(dir, exclude);
+        }
+
+        snapshot
+    }
+
+    fn record(&mut self, path: &Path, exclude: &[PathBuf]) {
+        if exclude.iter().any(|excluded| path.starts_with(excluded)) {
+            return;
+        }
+
+        let meta = match fs::symlink_metadata(path) {
+            Ok(meta) => meta,
+            Err(_) => return,
+        };
+
+        if !meta.is_dir() {
+            self.files
+                .insert(path.to_path_buf(), (meta.modified().ok(), meta.len()));
+            return;
+        }
+
+        for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+            self.record(&entry.path(), exclude);
+        }
+    }
+
+    /// Every file that has been created, modified or deleted since this snapshot, sorted.
+    pub fn changes(&self, after: &Snapshot) -> Vec<PathBuf> {
+        let mut changes = after
+            .files
+            .iter()
+            .filter(|(path, state)| self.files.get(*path) != Some(state))
+            .map(|(path, _)| path.clone())
+            .chain(
+                self.files
+                    .keys()
+                    .filter(|path| !after.files.contains_key(*path))
+                    .cloned(),
+            )
+            .collect::<Vec<PathBuf>>();
+
+        changes.sort();
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+
+        fs::create_dir_all(path("etc")).unwrap();
+        fs::create_dir_all(path("build")).unwrap();
+        fs::write(path("etc/kept"), "").unwrap();
+        fs::write(path("etc/modified"), "").unwrap();
+        fs::write(path("etc/deleted"), "").unwrap();
+
+        let dirs = [dir.path().to_path_buf()];
+        let exclude = [path("build")];
+        let before = Snapshot::take(&dirs, &exclude);
+
+        fs::write(path("etc/modified"), "changed").unwrap();
+        fs::remove_file(path("etc/deleted")).unwrap();
+        fs::write(path("etc/created"), "").unwrap();
+        fs::write(path("build/ignored"), "").unwrap();
+
+        assert_eq!(
+            before.changes(&Snapshot::take(&dirs, &exclude)),
+            vec![
+                path("etc/created"),
+                path("etc/deleted"),
+                path("etc/modified")
+            ]
+        );
+    }
+}
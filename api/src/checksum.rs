@@ -0,0 +1,129 @@
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+// The files are streamed in chunks of this size, so hashing a multi-gigabyte
+// source only ever holds a single chunk in memory per thread.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Computes the hex encoded SHA-256 checksum of a file.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        let read = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Computes the checksums of several files in parallel, one file per task,
+/// spread over the available cores.
+///
+/// The results are in the same order as the given paths.
+pub fn sha256_files(paths: &[PathBuf]) -> Vec<io::Result<String>> {
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(paths.len());
+
+    if threads <= 1 {
+        return paths.iter().map(|path| sha256_file(path)).collect();
+    }
+
+    // every thread picks the next file that hasn't been hashed yet,
+    // so a single large file doesn't hold back the rest of the files.
+    let next = AtomicUsize::new(0);
+
+    let mut results = thread::scope(|scope| {
+        let handles = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::<(usize, io::Result<String>)>::new();
+
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+
+                        match paths.get(index) {
+                            Some(path) => results.push((index, sha256_file(path))),
+                            None => break,
+                        }
+                    }
+
+                    results
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Checksum thread panicked"))
+            .collect::<Vec<(usize, io::Result<String>)>>()
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn hashes_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc");
+
+        fs::write(&path, "abc").unwrap();
+
+        assert_eq!(
+            sha256_file(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut paths = (0..8)
+            .map(|i| {
+                let path = dir.path().join(i.to_string());
+                fs::write(&path, vec![i as u8; CHUNK_SIZE * i + 7]).unwrap();
+                path
+            })
+            .collect::<Vec<PathBuf>>();
+
+        paths.push(dir.path().join("missing"));
+
+        let parallel = sha256_files(&paths);
+
+        assert_eq!(parallel.len(), paths.len());
+
+        for (path, result) in paths.iter().zip(parallel) {
+            match sha256_file(path) {
+                Ok(expected) => assert_eq!(result.unwrap(), expected),
+                Err(_) => assert!(result.is_err()),
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use crate::warnings::warn;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+// Every known setting, as (key, environment variable, default value).
+const SETTINGS: &[(&str, &str, &str)] = &[
+    (
+        "repos",
+        "PUR_PATH",
+        "/usr/repo/pur:/usr/repo/pur-community:/usr/repo/unofficial",
+    ),
+    ("root", "PUR_ROOT", "/"),
+    ("prefix", "PUR_PREFIX", "/usr"),
+    ("verbose_scripts", "PUR_VERBOSE_SCRIPTS", "no"),
+    ("clean_env", "PUR_CLEAN_ENV", "no"),
+    ("build_logs", "PUR_BUILD_LOGS", "5"),
+    ("noninteractive", "PUR_NONINTERACTIVE", "no"),
+    ("assume_yes", "PUR_ASSUME_YES", "no"),
+    ("verbose", "PUR_VERBOSE", "no"),
+    ("build_dir", "PUR_BUILD_DIR", "/var/tmp/pur/build"),
+    ("upgrade_after_update", "PUR_UPGRADE_AFTER_UPDATE", "yes"),
+    ("on_conflict", "PUR_ON_CONFLICT", "abort"),
+    ("stale_days", "PUR_STALE_DAYS", "30"),
+    ("staleness_check", "PUR_STALENESS_CHECK", "yes"),
+    ("profile", "PUR_PROFILE", "release"),
+    ("audit_scripts", "PUR_AUDIT_SCRIPTS", "no"),
+    ("audit_dirs", "PUR_AUDIT_DIRS", "/etc:/usr:/opt:/var"),
+    ("color_diagnostics", "PUR_COLOR_DIAGNOSTICS", "auto"),
+    ("install_script", "PUR_INSTALL_SCRIPT", "install"),
+    ("build_script", "PUR_BUILD_SCRIPT", "build"),
+    ("package_script", "PUR_PACKAGE_SCRIPT", "package"),
+    ("fallback_binary", "PUR_FALLBACK_BINARY", "no"),
+    ("umask", "PUR_UMASK", "022"),
+    ("update_script", "PUR_UPDATE_SCRIPT", "update"),
+    ("time", "PUR_TIME", "no"),
+    ("features", "PUR_FEATURES", ""),
+    ("upgrade_deps", "PUR_UPGRADE_DEPS", "no"),
+    ("ldconfig", "PUR_LDCONFIG", "yes"),
+    ("db_root", "PUR_DB_ROOT", "/var/db/installed"),
+    ("cache_dir", "PUR_CACHE_DIR", "/var/cache/pur"),
+    ("jobs", "PUR_JOBS", "1"),
+    ("max_rate", "PUR_MAX_RATE", "0"),
+];
+
+// The config file within a repository, for settings that depend on the conventions of the repository.
+pub const REPO_CONFIG: &str = ".pur.conf";
+
+// The configuration of this process, see [Config::load].
+static LOADED: Mutex<Option<Config>> = Mutex::new(None);
+
+/// Where the value of a setting came from.
+///
+/// The variants are ordered by precedence, a later source always overrides an earlier one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    Default,
+    File,
+    Env,
+    Cli,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Source::Default => "default",
+            Source::File => "config file",
+            Source::Env => "env",
+            Source::Cli => "cli",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Setting {
+    pub key: &'static str,
+    pub value: String,
+    pub source: Source,
+}
+
+/// The effective configuration, layered from the defaults, the config file,
+/// and the environment variables (in that order).
+#[derive(Debug, Clone)]
+pub struct Config {
+    settings: Vec<Setting>,
+    file: HashMap<String, String>,
+}
+
+impl Config {
+    /// Loads the configuration from the config file at [config_path] and the environment.
+    ///
+    /// Both are only read once, every later call returns the same configuration until it's
+    /// invalidated, see [Config::invalidate].
+    pub fn load() -> Self {
+        LOADED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert_with(Self::read)
+            .clone()
+    }
+
+    /// Drops the loaded configuration, so the next [Config::load] reads it again,
+    /// e.g. once a front-end changed the environment.
+    pub fn invalidate() {
+        *LOADED.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    fn read() -> Self {
+        let file = read_config_file(&config_path()).unwrap_or_default();
+
+        Self::layer(&file, &|var| std::env::var(var).ok())
+    }
+
+    /// Layers the settings of a parsed config file and the environment on top of the defaults.
+    pub fn layer(file: &HashMap<String, String>, env: &dyn Fn(&str) -> Option<String>) -> Self {
+        let settings = SETTINGS
+            .iter()
+            .map(|(key, var, default)| {
+                let mut setting = Setting {
+                    key,
+                    value: default.to_string(),
+                    source: Source::Default,
+                };
+
+                if let Some(value) = file.get(*key) {
+                    setting.value = value.clone();
+                    setting.source = Source::File;
+                }
+
+                // empty environment variables are treated as if they weren't set.
+                if let Some(value) = env(var).filter(|value| !value.is_empty()) {
+                    setting.value = value;
+                    setting.source = Source::Env;
+                }
+
+                setting
+            })
+            .collect::<Vec<Setting>>();
+
+        Self {
+            settings,
+            file: file.clone(),
+        }
+    }
+
+    pub fn settings(&self) -> &[Setting] {
+        &self.settings
+    }
+
+    /// The value of a known setting, see [SETTINGS].
+    pub fn value(&self, key: &str) -> &str {
+        self.settings
+            .iter()
+            .find(|setting| setting.key == key)
+            .map(|setting| setting.value.as_str())
+            .unwrap_or_else(|| panic!("Unknown setting {}", key))
+    }
+
+    /// The value of a known boolean setting, which is set by `yes`, `true`, `on` or `1`.
+    pub fn flag(&self, key: &str) -> bool {
+        is_enabled(self.value(key))
+    }
+
+    /// Overrides a setting, e.g. with a value given on the command line.
+    pub fn set(&mut self, key: &str, value: &str, source: Source) {
+        if let Some(setting) = self.settings.iter_mut().find(|setting| setting.key == key) {
+            setting.value = value.to_owned();
+            setting.source = source;
+        }
+    }
+
+    /// The root directory packages are installed into, see [install_root].
+    pub fn install_root(&self) -> PathBuf {
+        let root = PathBuf::from(self.value("root"));
+
+        std::path::absolute(&root).unwrap_or(root)
+    }
+
+    /// The name of a script of the packages within a repository, see [script_name].
+    pub fn script_name(&self, repo: &Path, key: &str) -> String {
+        read_config_file(&repo.join(REPO_CONFIG))
+            .ok()
+            .and_then(|file| file.get(key).cloned())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| self.value(key).to_owned())
+    }
+
+    /// The environment variables of the build `profile`, which are defined within the config file
+    /// through `profile.<name>.<VARIABLE> = value` lines, e.g. `profile.debug.CFLAGS = -O0 -g`.
+    ///
+    /// A profile without any variables is fine, install scripts still get its name through `PUR_PROFILE`.
+    pub fn profile_env(&self) -> HashMap<String, String> {
+        profile_vars(&self.file, self.value("profile"))
+    }
+}
+
+/// The environment variable of a known setting, see [SETTINGS].
+pub fn env_var(key: &str) -> &'static str {
+    SETTINGS
+        .iter()
+        .find(|(setting, _, _)| *setting == key)
+        .map(|(_, var, _)| *var)
+        .unwrap_or_else(|| panic!("Unknown setting {}", key))
+}
+
+/// Whether a boolean value is enabled, which is set by `yes`, `true`, `on` or `1`.
+pub fn is_enabled(value: &str) -> bool {
+    matches!(
+        value.trim().to_lowercase().as_str(),
+        "yes" | "true" | "on" | "1"
+    )
+}
+
+/// The name of a script of the packages within a repository, e.g. the `install_script`.
+///
+/// Repositories name their scripts by their own convention, so the [REPO_CONFIG] of the repository
+/// takes precedence over the global setting.
+pub fn script_name(repo: &Path, key: &str) -> String {
+    Config::load().script_name(repo, key)
+}
+
+/// The path of the config file.
+///
+/// This reads the `PUR_CONFIG` environment variable, and falls back to `/etc/pur.conf`.
+pub fn config_path() -> PathBuf {
+    match std::env::var("PUR_CONFIG") {
+        Ok(val) if !val.is_empty() => PathBuf::from(val),
+        _ => PathBuf::from("/etc/pur.conf"),
+    }
+}
+
+/// Parses a config file made of `key = value` lines.
+///
+/// Empty lines and lines starting with `#` are ignored.
+pub fn read_config_file(path: &Path) -> std::io::Result<HashMap<String, String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .flat_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+        .collect::<HashMap<String, String>>())
+}
+
+fn profile_vars(file: &HashMap<String, String>, name: &str) -> HashMap<String, String> {
+    let prefix = format!("profile.{}.", name);
+
+    file.iter()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(&prefix)
+                .filter(|var| !var.is_empty())
+                .map(|var| (var.to_owned(), value.clone()))
+        })
+        .collect()
+}
+
+/// The features that enable conditional dependencies, e.g. `x11` for a `x11: libxcb` line
+/// within a depends file.
+///
+/// This is the comma separated `features` setting, which the front-end's `--enable` flag adds to.
+/// The build profile counts as enabled too, so e.g. `debug: gdb` only applies to debug builds.
+pub fn enabled_features() -> Vec<String> {
+    let config = Config::load();
+
+    config
+        .value("features")
+        .split(',')
+        .chain([config.value("profile")])
+        .map(str::trim)
+        .filter(|feature| !feature.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// The root directory packages are installed into.
+///
+/// This is the `root` setting, which is `/` unless it's overridden through the config file
+/// or `PUR_ROOT`. The front-end's `--root` flag sets this variable, so a tree can be staged
+/// under e.g. `/mnt` and be mounted at `/` later.
+///
+/// A relative root is resolved against the current directory, so it's always absolute.
+/// Install scripts get the resolved root through the `PUR_ROOT` environment variable.
+pub fn install_root() -> PathBuf {
+    Config::load().install_root()
+}
+
+/// The prefix packages are built for, e.g. what's passed to `./configure --prefix`.
+///
+/// This is the `prefix` setting, which is `/usr` unless it's overridden through the config
+/// file or `PUR_PREFIX`. Install scripts get it through the `PUR_PREFIX` environment variable.
+pub fn install_prefix() -> PathBuf {
+    PathBuf::from(Config::load().value("prefix"))
+}
+
+/// The directory packages are built in, every package gets its own directory within it.
+///
+/// This is the `build_dir` setting, which is `/var/tmp/pur/build` unless it's overridden through
+/// the config file or `PUR_BUILD_DIR`, e.g. to build on a faster or larger file system.
+pub fn build_dir() -> PathBuf {
+    PathBuf::from(Config::load().value("build_dir"))
+}
+
+/// The install database, every built package gets its own directory within it.
+///
+/// This is the `db_root` setting, which is `/var/db/installed` unless it's overridden through
+/// the config file or `PUR_DB_ROOT`, e.g. for per-user installs together with `PUR_ROOT`.
+pub fn db_root() -> PathBuf {
+    PathBuf::from(Config::load().value("db_root"))
+}
+
+/// Where pur keeps data it can rebuild at any time, e.g. the package indexes of the repositories.
+///
+/// This is the `cache_dir` setting, which is `/var/cache/pur` unless it's overridden through
+/// the config file or `PUR_CACHE_DIR`.
+pub fn cache_dir() -> PathBuf {
+    PathBuf::from(Config::load().value("cache_dir"))
+}
+
+/// Sets the umask of the process to the octal `umask` setting, which is `022` by default.
+///
+/// The permissions of the install database, the build directories and the files install scripts
+/// create (scripts inherit the umask) shouldn't depend on the umask of whatever shell pur happens
+/// to be invoked from, e.g. they should never end up world-writable. An invalid umask is warned
+/// about, and `022` is used instead.
+pub fn apply_umask() {
+    let value = Config::load().value("umask").to_owned();
+
+    let mask = parse_umask(&value).unwrap_or_else(|| {
+        warn(format!("umask {} is invalid, using 022 instead", value));
+        0o022
+    });
+
+    // SAFETY: umask(2) can't fail, it only swaps the mask of the process.
+    unsafe {
+        libc::umask(mask);
+    }
+}
+
+fn parse_umask(value: &str) -> Option<libc::mode_t> {
+    libc::mode_t::from_str_radix(value.trim(), 8)
+        .ok()
+        .filter(|mask| *mask <= 0o777)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_sources_take_precedence() {
+        let file = HashMap::from([
+            ("repos".to_owned(), "/srv/repo".to_owned()),
+            ("root".to_owned(), "/mnt".to_owned()),
+        ]);
+
+        let env = |var: &str| match var {
+            "PUR_ROOT" => Some("/tmp/root".to_owned()),
+            "PUR_PATH" => Some("".to_owned()),
+            _ => None,
+        };
+
+        let mut config = Config::layer(&file, &env);
+
+        assert_eq!(config.value("repos"), "/srv/repo");
+        assert_eq!(config.settings()[0].source, Source::File);
+        assert_eq!(config.value("root"), "/tmp/root");
+        assert_eq!(config.settings()[1].source, Source::Env);
+
+        config.set("root", "/stage", Source::Cli);
+
+        assert_eq!(config.value("root"), "/stage");
+        assert_eq!(config.settings()[1].source, Source::Cli);
+    }
+
+    #[test]
+    fn repository_names_its_scripts() {
+        let repo = tempfile::tempdir().unwrap();
+        fs::write(repo.path().join(REPO_CONFIG), "install_script = build.sh\n").unwrap();
+
+        assert_eq!(script_name(repo.path(), "install_script"), "build.sh");
+        assert_eq!(script_name(repo.path(), "update_script"), "update");
+    }
+
+    #[test]
+    fn parses_umask() {
+        assert_eq!(parse_umask("022"), Some(0o022));
+        assert_eq!(parse_umask(" 0077\n"), Some(0o077));
+        assert_eq!(parse_umask("888"), None);
+        assert_eq!(parse_umask("1777"), None);
+    }
+
+    #[test]
+    fn parses_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pur.conf");
+
+        fs::write(&path, "# comment\n\nroot = /mnt\nrepos=/a:/b\n").unwrap();
+
+        let file = read_config_file(&path).unwrap();
+
+        assert_eq!(file.get("root").map(String::as_str), Some("/mnt"));
+        assert_eq!(file.get("repos").map(String::as_str), Some("/a:/b"));
+        assert_eq!(file.len(), 2);
+    }
+
+    #[test]
+    fn reads_profile_variables() {
+        let file = HashMap::from([
+            ("profile.debug.CFLAGS".to_owned(), "-O0 -g".to_owned()),
+            ("profile.debug.".to_owned(), "ignored".to_owned()),
+            ("profile.release.CFLAGS".to_owned(), "-O2".to_owned()),
+            ("root".to_owned(), "/mnt".to_owned()),
+        ]);
+
+        assert_eq!(
+            profile_vars(&file, "debug"),
+            HashMap::from([("CFLAGS".to_owned(), "-O0 -g".to_owned())])
+        );
+        assert!(profile_vars(&file, "custom").is_empty());
+    }
+}
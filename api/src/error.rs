@@ -1,5 +1,5 @@
 use macros::DebugDisplay;
-use std::{fmt::Display, io::ErrorKind};
+use std::{fmt::Display, io::ErrorKind, path::PathBuf};
 
 #[derive(Debug, DebugDisplay)]
 pub enum FileStructureError {
@@ -8,14 +8,39 @@ pub enum FileStructureError {
     SymLinkError(String),
     FileCopyError(String),
     NoPermission,
+    /// A file would be linked outside of the structure child it belongs to, e.g. through `../`.
+    PathEscape(PathBuf),
+    /// Links which collide with other files on a case-insensitive file system,
+    /// together with the files they collide with.
+    CaseCollision(Vec<(PathBuf, PathBuf)>),
     Other(String),
 }
 
-#[derive(Debug, DebugDisplay)]
+#[derive(Debug, Clone, DebugDisplay)]
 pub enum ExecuteError {
     NoDependFound,
     CompileFail,
     UninstallFail,
+    InvalidPackage,
+    UnknownRepository(String),
+    FileConflicts,
+    CleanFail,
+    /// The user declined to go ahead when asked.
+    Aborted,
+    ChecksumFail,
+    /// Packages that require themselves through their dependencies, e.g. `[a, b, a]`.
+    DependencyCycle(Vec<String>),
+    /// A package that's about to be removed is still required by other installed packages.
+    RequiredBy {
+        package: String,
+        dependents: Vec<String>,
+    },
+    /// The names the user gave which don't match any package within the repositories.
+    PackageNotFound(Vec<String>),
+    /// The packages to remove which aren't installed.
+    NotInstalled(Vec<String>),
+    /// An operation on the file system failed, e.g. as we're lacking permissions.
+    Io(String),
 }
 
 #[derive(Debug, DebugDisplay)]
@@ -24,15 +49,48 @@ pub enum ParseError {
     NoDirectory(String),
     AlreadyInstalled,
     NotInstalled,
-    NoInstallScript,
-    FailedInstallScript,
+    NoInstallScript {
+        package: String,
+        path: PathBuf,
+    },
+    InstallScriptNotExecutable {
+        package: String,
+        path: PathBuf,
+    },
+    /// An install script exited unsuccessfully, with the last lines it wrote to stderr.
+    FailedInstallScript(Vec<String>),
     NoDepends,
+    /// A source of the package doesn't match its checksum within the `checksums` file.
+    ChecksumMismatch {
+        file: String,
+        expected: String,
+        got: String,
+    },
+    ReadOnlyDatabase(String),
+    /// The name of the package directory isn't valid UTF-8, which package names have to be.
+    InvalidName(PathBuf),
+    /// A line of the `sources` file couldn't be expanded, see [crate::package::Package::sources].
+    InvalidSource {
+        line: usize,
+        reason: String,
+    },
+    /// A line of the `depends` file isn't a package name with an optional version constraint.
+    InvalidDepend {
+        line: usize,
+        reason: String,
+    },
     Other(String),
 }
 
 #[derive(Debug, DebugDisplay)]
 pub enum BuildError {
     LinkError,
+    ReadOnlyDatabase(String),
+    /// A file of another installed package is where a link would be created.
+    FileConflict {
+        path: PathBuf,
+        owner: String,
+    },
 }
 
 #[derive(Debug, DebugDisplay)]
@@ -40,6 +98,13 @@ pub enum UpdateError {
     NoUpdateScript,
     UpdateScriptError,
     PackageUpdateError(String),
+    /// Pulling a git repository failed, together with what git said.
+    GitPullError(String),
+    /// The changes pulled into a git repository conflict with its local changes, within these files.
+    /// The merge is aborted, so the repository is left as it was.
+    MergeConflict(Vec<String>),
+    /// Downloading a remote repository failed, see [crate::remote::mirror].
+    FetchError(String),
 }
 
 impl From<BuildError> for ParseError {
@@ -48,6 +113,8 @@ impl From<BuildError> for ParseError {
 
         match e {
             BuildError::LinkError => Self::Other(val),
+            BuildError::ReadOnlyDatabase(val) => Self::ReadOnlyDatabase(val),
+            BuildError::FileConflict { .. } => Self::Other(val),
         }
     }
 }
@@ -63,6 +130,12 @@ impl From<std::io::Error> for ParseError {
     }
 }
 
+impl From<std::io::Error> for ExecuteError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
 impl From<std::io::Error> for FileStructureError {
     fn from(e: std::io::Error) -> Self {
         let val = e.to_string();
@@ -75,3 +148,12 @@ impl From<std::io::Error> for FileStructureError {
         }
     }
 }
+
+impl From<ParseError> for BuildError {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::ReadOnlyDatabase(val) => Self::ReadOnlyDatabase(val),
+            _ => Self::LinkError,
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crate::config::Config;
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Mutex, PoisonError},
+    thread,
+    time::Duration,
+};
+
+// How often the progress of a download is reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Called with the URL and progress of a download, see [observe].
+pub type Observer = fn(&str, Progress);
+
+// Reports the progress of every download, see [observe].
+static OBSERVER: Mutex<Option<Observer>> = Mutex::new(None);
+
+/// How far along a download is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// The bytes downloaded so far.
+    pub downloaded: u64,
+    /// The size of the file, unless the server didn't send a `Content-Length`.
+    pub total: Option<u64>,
+    /// Whether the download is done, which is reported once.
+    pub done: bool,
+}
+
+/// Has `observer` called with the URL and progress of every download from now on,
+/// e.g. so front-ends can show a progress bar. See [reporter].
+pub fn observe(observer: Observer) {
+    *OBSERVER.lock().unwrap_or_else(PoisonError::into_inner) = Some(observer);
+}
+
+/// A progress callback for [download] which passes the progress on to the registered observer,
+/// if any, see [observe].
+pub fn reporter(url: &str) -> impl FnMut(Progress) + '_ {
+    move |progress| {
+        let observer = *OBSERVER.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(observer) = observer {
+            observer(url, progress);
+        }
+    }
+}
+
+/// Downloads the file at the given URL into `target`, calling `progress` every now and then
+/// while downloading, and once it's done.
+///
+/// Failing HTTP responses are errors, rather than being written into `target`.
+///
+/// The download is limited to --max-rate (the `max_rate` setting) KB/s, unless that's 0.
+pub fn download(url: &str, target: &Path, mut progress: impl FnMut(Progress)) -> io::Result<()> {
+    let headers = headers_path(target);
+
+    let mut command = Command::new("curl");
+    command
+        .args([
+            "--fail",
+            "--location",
+            "--silent",
+            "--show-error",
+            "--dump-header",
+        ])
+        .arg(&headers)
+        .arg("--output")
+        .arg(target);
+
+    if let Ok(rate @ 1..) = Config::load().value("max_rate").parse::<u64>() {
+        command.arg("--limit-rate").arg(format!("{}k", rate));
+    }
+
+    let mut child = command.arg(url).stdin(Stdio::null()).spawn()?;
+
+    // curl writes the file as it goes, so its size is how much was downloaded.
+    let current = |done| Progress {
+        downloaded: fs::metadata(target).map(|data| data.len()).unwrap_or(0),
+        total: fs::read_to_string(&headers)
+            .ok()
+            .and_then(|headers| content_length(&headers)),
+        done,
+    };
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        progress(current(false));
+        thread::sleep(PROGRESS_INTERVAL);
+    };
+
+    if status.success() {
+        progress(current(true));
+    }
+
+    let _ = fs::remove_file(&headers);
+
+    if !status.success() {
+        let _ = fs::remove_file(target);
+
+        return Err(io::Error::other(format!("couldn't download {}", url)));
+    }
+
+    Ok(())
+}
+
+// The file curl writes the response headers of a download into, next to the downloaded file.
+fn headers_path(target: &Path) -> PathBuf {
+    let mut path = OsString::from(target);
+    path.push(".headers");
+
+    PathBuf::from(path)
+}
+
+// The `Content-Length` of the last response within the headers curl wrote, as following
+// redirects writes the headers of every response one after another.
+fn content_length(headers: &str) -> Option<u64> {
+    let mut length = None;
+
+    for line in headers.lines() {
+        if line.starts_with("HTTP/") {
+            length = None;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    length
+}
+
+/// Extracts a tarball into `target`, whatever it's compressed with.
+pub fn extract(archive: &Path, target: &Path) -> io::Result<()> {
+    fs::create_dir_all(target)?;
+
+    let status = Command::new("tar")
+        .arg("-xf")
+        .arg(archive)
+        .arg("-C")
+        .arg(target)
+        .status()?;
+
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "couldn't extract {}",
+            archive.display()
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_length_of_the_last_response() {
+        let headers = "HTTP/1.1 302 Found\r\nContent-Length: 12\r\nLocation: /file\r\n\r\n\
+                       HTTP/1.1 200 OK\r\ncontent-length: 4096\r\n\r\n";
+
+        assert_eq!(content_length(headers), Some(4096));
+    }
+
+    #[test]
+    fn has_no_length_without_content_length() {
+        let headers = "HTTP/1.1 302 Found\r\nContent-Length: 12\r\n\r\n\
+                       HTTP/2 200\r\ntransfer-encoding: chunked\r\n\r\n";
+
+        assert_eq!(content_length(headers), None);
+        assert_eq!(content_length(""), None);
+    }
+}
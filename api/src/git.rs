@@ -0,0 +1,175 @@
+use crate::error::UpdateError;
+use crate::package::definition_lines;
+use std::{
+    fs, io,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// The git ref (commit, tag, ...) a package definition is pinned to, read from its `ref` file.
+///
+/// Pinned packages are built from their definition at that ref instead of the current one,
+/// so `pur update` can't pull in an unreviewed definition.
+pub fn read_pin(dir: &Path) -> Option<String> {
+    fs::read_to_string(dir.join("ref"))
+        .ok()
+        .and_then(|content| definition_lines(&content).next().map(String::from))
+}
+
+/// Reads a file of the package definition within `dir`, as it was at the given ref.
+pub fn show(dir: &Path, rev: &str, file: &str) -> io::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["show", &format!("{}:./{}", rev, file)])
+        .stderr(Stdio::null())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} doesn't exist at {} within {}",
+            file,
+            rev,
+            dir.display()
+        )));
+    }
+
+    String::from_utf8(output.stdout).map_err(io::Error::other)
+}
+
+/// Writes the package definition within `dir` as it was at the given ref into `target`.
+pub fn export(dir: &Path, rev: &str, target: &Path) -> io::Result<()> {
+    fs::create_dir_all(target)?;
+
+    let mut archive = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["archive", "--format=tar", rev, "."])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let extracted = Command::new("tar")
+        .arg("-x")
+        .arg("-C")
+        .arg(target)
+        .stdin(
+            archive
+                .stdout
+                .take()
+                .ok_or_else(|| io::Error::other("no archive"))?,
+        )
+        .status()?;
+
+    if !archive.wait()?.success() || !extracted.success() {
+        return Err(io::Error::other(format!(
+            "couldn't export {} at {}",
+            dir.display(),
+            rev
+        )));
+    }
+
+    Ok(())
+}
+
+/// Pulls the changes of the upstream branch into the git checkout at `dir`, merging them with
+/// its local changes if need be.
+///
+/// A merge that conflicts is aborted, so the checkout is left as it was instead of with
+/// conflict markers within its packages.
+pub fn pull(dir: &Path) -> Result<(), UpdateError> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            // there's nobody to answer a prompt for credentials while packages are being updated.
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .output()
+    };
+
+    let output = git(&["pull", "--no-rebase", "--no-edit"])
+        .map_err(|e| UpdateError::GitPullError(e.to_string()))?;
+
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let conflicts = git(&["diff", "--name-only", "--diff-filter=U"])
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        .unwrap_or_default()
+        .lines()
+        .map(String::from)
+        .collect::<Vec<String>>();
+
+    if !conflicts.is_empty() {
+        let _ = git(&["merge", "--abort"]);
+        return Err(UpdateError::MergeConflict(conflicts));
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    Err(UpdateError::GitPullError(
+        stderr.lines().last().unwrap_or_default().trim().to_owned(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pulls_and_aborts_conflicts() {
+        let upstream = tempfile::tempdir().unwrap();
+        let checkout = tempfile::tempdir().unwrap();
+        let git = |dir: &Path, args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(["-c", "user.name=pur", "-c", "user.email=pur@localhost"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        let commit = |dir: &Path, version: &str| {
+            fs::write(dir.join("curl/version"), version).unwrap();
+            git(dir, &["commit", "-q", "-am", version]);
+        };
+
+        fs::create_dir_all(upstream.path().join("curl")).unwrap();
+        fs::write(upstream.path().join("curl/version"), "8.0").unwrap();
+        git(upstream.path(), &["init", "-q"]);
+        git(upstream.path(), &["add", "."]);
+        git(upstream.path(), &["commit", "-q", "-m", "8.0"]);
+        git(
+            checkout.path(),
+            &["clone", "-q", &upstream.path().to_string_lossy(), "."],
+        );
+        // merging creates a commit, which needs an identity.
+        git(checkout.path(), &["config", "user.name", "pur"]);
+        git(checkout.path(), &["config", "user.email", "pur@localhost"]);
+
+        commit(upstream.path(), "8.1");
+        pull(checkout.path()).unwrap();
+        assert_eq!(
+            fs::read_to_string(checkout.path().join("curl/version")).unwrap(),
+            "8.1"
+        );
+
+        commit(upstream.path(), "8.2");
+        commit(checkout.path(), "8.1-patched");
+
+        assert!(matches!(
+            pull(checkout.path()),
+            Err(UpdateError::MergeConflict(files)) if files == ["curl/version"]
+        ));
+        assert_eq!(
+            fs::read_to_string(checkout.path().join("curl/version")).unwrap(),
+            "8.1-patched"
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use crate::git;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// The first line of every index, which changes whenever the layout of the index
+// or the files within it do.
+const HEADER: &str = "pur-index 2";
+
+// The files a package is parsed from, see [crate::package::Package]. Scripts aren't among them,
+// as those are only read once the package is built.
+const DEFINITION_FILES: [&str; 7] = [
+    "version", "depends", "aliases", "meta", "linkdirs", "paths", "binary",
+];
+
+/// The files of a package directory that make up its definition, as they were read,
+/// so the package can be parsed without reading its directory again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub dir: PathBuf,
+    /// The git ref the definition is pinned to, see [git::read_pin].
+    pub pin: Option<String>,
+    files: HashMap<String, String>,
+    /// When the definition last changed as it was read, see [stamp].
+    stamp: Option<SystemTime>,
+}
+
+impl Definition {
+    /// Reads the definition of the package within `dir`, at its pinned ref if it has one.
+    pub fn read(dir: &Path) -> Self {
+        // taken before reading, so a change while reading makes it stale right away.
+        let stamp = stamp(dir);
+        let pin = git::read_pin(dir);
+        let files = DEFINITION_FILES
+            .iter()
+            .flat_map(|file| {
+                let content = match &pin {
+                    Some(pin) => git::show(dir, pin, file),
+                    None => fs::read_to_string(dir.join(file)),
+                };
+
+                content.ok().map(|content| (file.to_string(), content))
+            })
+            .collect();
+
+        Self {
+            dir: dir.to_path_buf(),
+            pin,
+            files,
+            stamp,
+        }
+    }
+
+    /// The content of a file of the definition, which fails like reading it would when it's missing.
+    pub fn file(&self, name: &str) -> io::Result<String> {
+        self.files.get(name).cloned().ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} doesn't exist within {}", name, self.dir.display()),
+            )
+        })
+    }
+}
+
+/// The definitions of every package within a repository, read from its index within `cache`
+/// whenever that's still fresh, so commands don't have to read every package directory each time.
+///
+/// An index is stale as soon as the modification time of the repository directory changes,
+/// i.e. whenever a package is added or removed. A package that's changed in place is read again
+/// on its own, as its definition is stale once its [stamp] changes, which only takes a few stats.
+pub fn load(repo: &Path, cache: &Path) -> io::Result<Vec<Definition>> {
+    let mtime = fs::metadata(repo)?.modified()?;
+
+    let cached = fs::read_to_string(index_path(repo, cache))
+        .ok()
+        .and_then(|content| parse(&content, repo))
+        .filter(|(cached, _)| *cached == mtime);
+
+    let mut definitions = match cached {
+        Some((_, definitions)) => definitions,
+        None => return rebuild(repo, cache),
+    };
+
+    let mut changed = false;
+
+    for definition in &mut definitions {
+        if stamp(&definition.dir) != definition.stamp {
+            *definition = Definition::read(&definition.dir);
+            changed = true;
+        }
+    }
+
+    if changed {
+        write(repo, cache, mtime, &definitions);
+    }
+
+    Ok(definitions)
+}
+
+/// Reads the definition of every package within a repository, and writes them into its index.
+///
+/// Files and hidden directories (e.g. `.git`) aren't packages, so they're left out. The index is
+/// only a cache, so the definitions are still returned when it can't be written, e.g. as non-root.
+pub fn rebuild(repo: &Path, cache: &Path) -> io::Result<Vec<Definition>> {
+    let mtime = fs::metadata(repo)?.modified()?;
+    let mut definitions = Vec::<Definition>::new();
+
+    for entry in fs::read_dir(repo)?.flatten() {
+        let path = entry.path();
+
+        if !path.is_dir() || entry.file_name().as_encoded_bytes().starts_with(b".") {
+            continue;
+        }
+
+        definitions.push(Definition::read(&path));
+    }
+
+    write(repo, cache, mtime, &definitions);
+
+    Ok(definitions)
+}
+
+// Writes the index, which is only a cache, so failing to do so is fine.
+fn write(repo: &Path, cache: &Path, mtime: SystemTime, definitions: &[Definition]) {
+    if let Some(content) = serialize(mtime, definitions) {
+        let _ = fs::create_dir_all(cache).and_then(|_| {
+            // the index is swapped in at once, so a command running alongside never reads half of it.
+            let path = index_path(repo, cache);
+            let partial = path.with_extension("partial");
+
+            fs::write(&partial, content)?;
+            fs::rename(partial, path)
+        });
+    }
+}
+
+/// When the definition of the package within `dir` last changed, which is the latest modification
+/// time of its directory and the files it's read from. Files that are written in place don't
+/// change the modification time of their directory, so those are looked at as well.
+fn stamp(dir: &Path) -> Option<SystemTime> {
+    [".", "ref"]
+        .iter()
+        .chain(DEFINITION_FILES.iter())
+        .flat_map(|file| fs::metadata(dir.join(file)).and_then(|meta| meta.modified()))
+        .max()
+}
+
+fn format_time(time: SystemTime) -> Option<String> {
+    let since = time.duration_since(UNIX_EPOCH).ok()?;
+
+    Some(format!("{}.{:09}", since.as_secs(), since.subsec_nanos()))
+}
+
+fn parse_time(value: &str) -> Option<SystemTime> {
+    let (secs, nanos) = value.split_once('.')?;
+
+    Some(UNIX_EPOCH + Duration::new(secs.parse().ok()?, nanos.parse().ok()?))
+}
+
+// Every repository gets its own index, named after its full path.
+fn index_path(repo: &Path, cache: &Path) -> PathBuf {
+    let name = repo
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace('/', "%2F");
+
+    cache.join(format!("{}.index", name))
+}
+
+// Every value is written as `<tag> [key] <length>` followed by the value on the next line,
+// so values can contain anything, including newlines.
+//
+// Returns None whenever a package name isn't valid UTF-8, which can't be written. Those
+// are invalid packages anyway, which are reported on every scan without an index.
+fn serialize(mtime: SystemTime, definitions: &[Definition]) -> Option<String> {
+    let mut content = format!("{}\nmtime {}\n", HEADER, format_time(mtime)?);
+
+    let mut value = |tag: &str, value: &str| {
+        content.push_str(&format!("{} {}\n{}\n", tag, value.len(), value));
+    };
+
+    for definition in definitions {
+        value("package", definition.dir.file_name()?.to_str()?);
+
+        if let Some(pin) = &definition.pin {
+            value("ref", pin);
+        }
+
+        if let Some(stamp) = definition.stamp.and_then(format_time) {
+            value("stamp", &stamp);
+        }
+
+        let mut files = definition.files.iter().collect::<Vec<(&String, &String)>>();
+        files.sort();
+
+        for (file, content) in files {
+            value(&format!("file {}", file), content);
+        }
+    }
+
+    Some(content)
+}
+
+// Parses an index written by [serialize], any index that's malformed is treated as stale.
+fn parse(content: &str, repo: &Path) -> Option<(SystemTime, Vec<Definition>)> {
+    let mut rest = content.strip_prefix(HEADER)?.strip_prefix('\n')?;
+
+    let mtime = parse_time(line(&mut rest)?.strip_prefix("mtime ")?)?;
+
+    let mut definitions = Vec::<Definition>::new();
+
+    while !rest.is_empty() {
+        let (tag, len) = line(&mut rest)?.rsplit_once(' ')?;
+        let len = len.parse::<usize>().ok()?;
+
+        let value = rest.get(..len)?.to_owned();
+        rest = rest.get(len..)?.strip_prefix('\n')?;
+
+        match tag.split_once(' ') {
+            None if tag == "package" => definitions.push(Definition {
+                dir: repo.join(value),
+                pin: None,
+                files: HashMap::new(),
+                stamp: None,
+            }),
+            None if tag == "ref" => definitions.last_mut()?.pin = Some(value),
+            None if tag == "stamp" => definitions.last_mut()?.stamp = Some(parse_time(&value)?),
+            Some(("file", file)) => {
+                definitions.last_mut()?.files.insert(file.to_owned(), value);
+            }
+            _ => return None,
+        }
+    }
+
+    Some((mtime, definitions))
+}
+
+fn line<'a>(rest: &mut &'a str) -> Option<&'a str> {
+    let (line, remaining) = rest.split_once('\n')?;
+    *rest = remaining;
+    Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_definitions() {
+        let repo = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let path = |name: &str| repo.path().join(name);
+
+        fs::create_dir_all(path("curl")).unwrap();
+        fs::create_dir_all(path(".git")).unwrap();
+        fs::write(path("curl/version"), "8.0").unwrap();
+        fs::write(path("curl/depends"), "zlib\nopenssl 3\n").unwrap();
+
+        let built = rebuild(repo.path(), cache.path()).unwrap();
+        let (_, cached) = parse(
+            &fs::read_to_string(index_path(repo.path(), cache.path())).unwrap(),
+            repo.path(),
+        )
+        .unwrap();
+
+        assert_eq!(built.len(), 1);
+        assert_eq!(cached, built);
+        assert_eq!(cached[0].file("depends").unwrap(), "zlib\nopenssl 3\n");
+        assert!(cached[0].file("aliases").is_err());
+
+        // packages changed in place are read again on their own, which doesn't touch the
+        // repository directory, so the rest of the index is still used.
+        std::thread::sleep(Duration::from_millis(10));
+        fs::write(path("curl/version"), "8.1").unwrap();
+        assert_eq!(
+            load(repo.path(), cache.path()).unwrap()[0]
+                .file("version")
+                .unwrap(),
+            "8.1"
+        );
+
+        // while added packages change the repository directory, which makes the index stale.
+        std::thread::sleep(Duration::from_millis(10));
+        fs::create_dir_all(path("zlib")).unwrap();
+
+        let loaded = load(repo.path(), cache.path()).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded
+            .iter()
+            .any(|definition| definition.dir == path("zlib")));
+    }
+}
@@ -1,7 +1,22 @@
+pub mod audit;
+pub mod checksum;
+pub mod config;
 pub mod error;
+pub mod fetch;
+pub mod git;
+pub mod index;
+pub mod linker;
+pub mod manifest;
 pub mod package;
+pub mod remote;
 pub mod repo;
+pub mod script;
 pub mod structure;
+pub mod version;
+pub mod warnings;
+
+/// The version of this library, which may differ from the version of the pur binary using it.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg(test)]
 mod tests {}
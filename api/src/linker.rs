@@ -0,0 +1,78 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::config::{install_root, Config};
+
+// The directories within the install root the dynamic linker looks for libraries in.
+const LIBRARY_DIRS: [&str; 6] = ["lib", "lib32", "lib64", "usr/lib", "usr/lib32", "usr/lib64"];
+
+// Whether a library directory changed since the linker cache was last refreshed.
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Records the links that were created or removed within the root, so the linker cache
+/// is refreshed once the command is done whenever any of them is within a library directory.
+pub fn record(root: &Path, paths: &[PathBuf]) {
+    if paths.iter().any(|path| is_library_path(root, path)) {
+        CHANGED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Runs `ldconfig` whenever a library directory changed, see [record], so freshly installed
+/// libraries are found right away. This is meant to be run once at the end of a command rather
+/// than per package, as ldconfig scans every library on the system.
+///
+/// It's turned off through --no-ldconfig (the `ldconfig` setting). Systems without ldconfig,
+/// e.g. musl based ones, don't have a cache to refresh, so it's skipped there.
+///
+/// Returns whether the cache was refreshed.
+pub fn refresh() -> io::Result<bool> {
+    if !CHANGED.swap(false, Ordering::Relaxed) || !Config::load().flag("ldconfig") {
+        return Ok(false);
+    }
+
+    let root = install_root();
+    let mut command = Command::new("ldconfig");
+
+    // a staged root has a cache of its own.
+    if root != Path::new("/") {
+        command.arg("-r").arg(&root);
+    }
+
+    let status = match command.stdout(Stdio::null()).status() {
+        Ok(status) => status,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    if !status.success() {
+        return Err(io::Error::other(format!("ldconfig exited with {}", status)));
+    }
+
+    Ok(true)
+}
+
+fn is_library_path(root: &Path, path: &Path) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+
+    LIBRARY_DIRS.iter().any(|dir| relative.starts_with(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_library_paths() {
+        let root = Path::new("/mnt");
+
+        assert!(is_library_path(root, Path::new("/mnt/usr/lib/libfoo.so")));
+        assert!(is_library_path(root, Path::new("/mnt/lib64/ld-linux.so.2")));
+        assert!(!is_library_path(root, Path::new("/mnt/usr/bin/foo")));
+        assert!(!is_library_path(root, Path::new("/mnt/usr/libexec/foo")));
+        assert!(!is_library_path(root, Path::new("/mnt/usr/share/lib/foo")));
+    }
+}
@@ -0,0 +1,264 @@
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{self, Read, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Component, Path, PathBuf},
+};
+
+// The compressed manifest within an install database entry, see [Manifest::store].
+const COMPRESSED: &str = "manifest.gz";
+
+// The uncompressed manifest, of packages installed before manifests were compressed.
+const UNCOMPRESSED: &str = "manifest";
+
+/// The list of files a package has linked out of its install directory.
+///
+/// Entries are stored relative to the install root rather than as absolute paths,
+/// so a tree that was staged with `--root /mnt` and later mounted at `/` still has
+/// a valid manifest. Use [Manifest::resolve] to get the actual paths for a root.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    entries: Vec<PathBuf>,
+}
+
+impl Manifest {
+    /// Creates a manifest out of absolute paths that were created under `root`.
+    pub fn from_paths(root: &Path, paths: &[PathBuf]) -> Self {
+        let mut manifest = Self::default();
+
+        for path in paths {
+            manifest.push(root, path);
+        }
+
+        manifest
+    }
+
+    /// Adds a path to the manifest, stripping the root from it first.
+    ///
+    /// Paths that don't live under `root` are stored with their leading `/` removed,
+    /// so they're still resolved relative to whatever root is used at read time.
+    pub fn push(&mut self, root: &Path, path: &Path) {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+
+        let entry = relative
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect::<PathBuf>();
+
+        if entry.as_os_str().is_empty() {
+            return;
+        }
+
+        self.entries.push(entry);
+    }
+
+    /// The root-relative entries of this manifest.
+    pub fn entries(&self) -> &[PathBuf] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Resolves every entry against the given root.
+    pub fn resolve(&self, root: &Path) -> Vec<PathBuf> {
+        self.entries.iter().map(|entry| root.join(entry)).collect()
+    }
+
+    /// Reads a manifest of one entry per line.
+    ///
+    /// The entries are read as raw bytes, so file names which aren't valid UTF-8 are kept as they are.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read(path)?))
+    }
+
+    /// Writes the manifest as one entry per line, see [Manifest::read].
+    ///
+    /// Entries with a newline in their name can't be written, as they'd be read back as several entries.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes()?)
+    }
+
+    /// Reads the manifest of an entry of the install database, which is stored compressed as
+    /// `manifest.gz`. Packages installed before manifests were compressed have an uncompressed
+    /// `manifest` instead, which is read whenever there's no compressed one.
+    pub fn load(installed_dir: &Path) -> io::Result<Self> {
+        let compressed = installed_dir.join(COMPRESSED);
+
+        if !compressed.exists() {
+            return Self::read(&installed_dir.join(UNCOMPRESSED));
+        }
+
+        let mut bytes = Vec::<u8>::new();
+
+        GzDecoder::new(fs::File::open(&compressed)?)
+            .read_to_end(&mut bytes)
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("couldn't decompress {}: {}", compressed.display(), e),
+                )
+            })?;
+
+        Ok(Self::parse(&bytes))
+    }
+
+    /// Writes the manifest of an entry of the install database gzip-compressed, as manifests of
+    /// packages with tens of thousands of files get large. An uncompressed manifest is removed,
+    /// so only one of them is around.
+    pub fn store(&self, installed_dir: &Path) -> io::Result<()> {
+        let bytes = self.to_bytes()?;
+        let compressed = installed_dir.join(COMPRESSED);
+
+        // a partially written manifest would be read instead of the uncompressed one.
+        if let Err(e) = compress(&bytes, &compressed) {
+            let _ = fs::remove_file(&compressed);
+            return Err(e);
+        }
+
+        match fs::remove_file(installed_dir.join(UNCOMPRESSED)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> Self {
+        let entries = bytes
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.trim_ascii().is_empty())
+            .map(|line| PathBuf::from(OsStr::from_bytes(line)))
+            .collect::<Vec<PathBuf>>();
+
+        Self { entries }
+    }
+
+    fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::<u8>::new();
+
+        for entry in &self.entries {
+            let entry = entry.as_os_str().as_bytes();
+
+            if entry.contains(&b'\n') {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{:?} contains a newline", OsStr::from_bytes(entry)),
+                ));
+            }
+
+            bytes.extend_from_slice(entry);
+            bytes.push(b'\n');
+        }
+
+        Ok(bytes)
+    }
+}
+
+// Compresses the bytes into the target file, in the gzip format.
+fn compress(bytes: &[u8], target: &Path) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(fs::File::create(target)?, Compression::default());
+
+    encoder.write_all(bytes)?;
+    encoder.finish().map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_root_relative() {
+        let root = PathBuf::from("/mnt");
+        let manifest = Manifest::from_paths(
+            &root,
+            &[
+                PathBuf::from("/mnt/usr/bin/pfetch"),
+                PathBuf::from("/mnt/usr/lib/libfoo.so"),
+            ],
+        );
+
+        assert_eq!(
+            manifest.entries(),
+            &[
+                PathBuf::from("usr/bin/pfetch"),
+                PathBuf::from("usr/lib/libfoo.so")
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_non_unicode_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("manifest");
+        let name = PathBuf::from(OsStr::from_bytes(b"usr/share/caf\xe9"));
+
+        let manifest = Manifest::from_paths(Path::new("/"), &[Path::new("/").join(&name)]);
+        manifest.write(&file).unwrap();
+
+        assert_eq!(Manifest::read(&file).unwrap().entries(), [name]);
+
+        let newline = Manifest::from_paths(Path::new("/"), &[PathBuf::from("/usr/a\nb")]);
+        assert!(newline.write(&file).is_err());
+    }
+
+    #[test]
+    fn loads_compressed_and_older_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = Manifest::from_paths(
+            Path::new("/"),
+            &(0..50_000)
+                .map(|n| PathBuf::from(format!("/usr/share/data/{}/file{}", n % 100, n)))
+                .collect::<Vec<PathBuf>>(),
+        );
+
+        // installed before manifests were compressed.
+        manifest.write(&dir.path().join("manifest")).unwrap();
+        assert_eq!(Manifest::load(dir.path()).unwrap(), manifest);
+
+        // storing it again replaces the uncompressed one.
+        manifest.store(dir.path()).unwrap();
+
+        assert!(dir.path().join("manifest.gz").exists());
+        assert!(!dir.path().join("manifest").exists());
+        assert_eq!(Manifest::load(dir.path()).unwrap(), manifest);
+
+        assert!(Manifest::load(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn resolves_under_another_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("manifest");
+
+        // built while staging under /mnt...
+        let staged = Manifest::from_paths(
+            Path::new("/mnt"),
+            &[
+                PathBuf::from("/mnt/usr/bin/pfetch"),
+                PathBuf::from("/mnt/usr/sbin/init"),
+            ],
+        );
+        staged.write(&file).unwrap();
+
+        // ...and read back after being mounted at /.
+        let manifest = Manifest::read(&file).unwrap();
+
+        assert_eq!(manifest, staged);
+        assert_eq!(
+            manifest.resolve(Path::new("/")),
+            vec![
+                PathBuf::from("/usr/bin/pfetch"),
+                PathBuf::from("/usr/sbin/init")
+            ]
+        );
+        assert_eq!(
+            manifest.resolve(Path::new("/srv/chroot")),
+            vec![
+                PathBuf::from("/srv/chroot/usr/bin/pfetch"),
+                PathBuf::from("/srv/chroot/usr/sbin/init")
+            ]
+        );
+    }
+}
@@ -1,133 +1,566 @@
 use std::{
-    env::set_current_dir,
+    collections::{HashMap, HashSet},
+    fmt::Display,
     fs::{self, File},
-    io::Write,
-    path::PathBuf,
-    process::Command,
+    io::{self, ErrorKind, Write},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, PoisonError},
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
+    audit::Snapshot,
+    checksum,
+    config::{
+        build_dir, db_root, enabled_features, install_root, is_enabled, read_config_file, Config,
+    },
     error::{BuildError, ParseError},
+    fetch, git,
+    index::Definition,
+    linker,
+    manifest::Manifest,
     repo::InstallData,
-    structure::{FileStructure, InstallFileStructure},
+    script,
+    structure::{remove_links_into, FileStructure, InstallFileStructure},
+    version::{parse_version, Version, VersionReq},
+    warnings::warn,
 };
 
+/// A common problem within a package definition, found by [Package::validate].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationWarning {
+    NoInstallScript,
+    InstallScriptNotExecutable,
+    EmptyVersion,
+    MissingDepend(String),
+    DuplicateDepend(String),
+    /// A line of the `checksums` file, which isn't a sha256 checksum followed by a file name.
+    MalformedChecksum {
+        line: usize,
+        content: String,
+    },
+    /// A line of the `sources` file, which doesn't consist of a single source.
+    MalformedSource {
+        line: usize,
+        content: String,
+    },
+}
+
+impl Display for ValidationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoInstallScript => write!(f, "there is no install script"),
+            Self::InstallScriptNotExecutable => write!(f, "the install script is not executable"),
+            Self::EmptyVersion => write!(f, "the version file is empty"),
+            Self::MissingDepend(name) => write!(f, "dependency {} doesn't exist", name),
+            Self::DuplicateDepend(name) => {
+                write!(f, "dependency {} is listed more than once", name)
+            }
+            Self::MalformedChecksum { line, content } => {
+                write!(f, "checksums line {} is malformed: {}", line, content)
+            }
+            Self::MalformedSource { line, content } => {
+                write!(f, "sources line {} is malformed: {}", line, content)
+            }
+        }
+    }
+}
+
+/// The names of the built and installed packages within the install database.
+///
+/// Checking whether a package is installed used to scan the whole database every time, which adds
+/// up when checking hundreds of packages. Instead the database is scanned once, see [with_index].
+#[derive(Debug, Default)]
+pub struct InstalledIndex {
+    built: HashSet<String>,
+    installed: HashSet<String>,
+}
+
+impl InstalledIndex {
+    /// Scans the install database, a missing database simply means nothing is installed.
+    ///
+    /// Packages that are being removed are neither built nor installed anymore, and neither are
+    /// those without a version, which is only recorded once a build succeeded.
+    pub fn scan(db: &Path) -> Self {
+        let mut index = Self::default();
+
+        for entry in fs::read_dir(db).into_iter().flatten().flatten() {
+            let path = entry.path();
+
+            if !path.is_dir() || path.join("removing").exists() || !path.join("version").exists() {
+                continue;
+            }
+
+            // package names are valid UTF-8, so anything else isn't a package.
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+
+            if path.join("installed").exists() {
+                index.installed.insert(name.clone());
+            }
+
+            index.built.insert(name);
+        }
+
+        index
+    }
+
+    pub fn is_installed(&self, name: &str) -> bool {
+        self.installed.contains(name)
+    }
+
+    pub fn is_built(&self, name: &str) -> bool {
+        self.built.contains(name)
+    }
+}
+
+// The index of the install database, which is scanned the first time it's needed.
+// Everything modifying the database has to call [invalidate_index].
+static INDEX: Mutex<Option<InstalledIndex>> = Mutex::new(None);
+
+fn with_index<T>(f: impl FnOnce(&InstalledIndex) -> T) -> T {
+    let mut index = INDEX.lock().unwrap_or_else(PoisonError::into_inner);
+
+    f(index.get_or_insert_with(|| InstalledIndex::scan(&db_root())))
+}
+
+fn invalidate_index() {
+    *INDEX.lock().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// A file that's already present where a package wants to link one of its files to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub path: PathBuf,
+    /// The installed package the file belongs to, if it belongs to any.
+    pub owner: Option<String>,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.owner {
+            Some(owner) => write!(f, "{} is owned by {}", self.path.display(), owner),
+            None => write!(f, "{} already exists", self.path.display()),
+        }
+    }
+}
+
+/// A line of the depends file, the name of a package optionally followed by a constraint on
+/// its version, e.g. `openssl>=3.0`. See [VersionReq] for the operators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Depend {
+    pub name: String,
+    pub constraint: Option<VersionReq>,
+}
+
+impl Depend {
+    /// Whether the given version of the dependency satisfies it,
+    /// which any version does without a constraint.
+    pub fn is_satisfied_by(&self, version: &Version) -> bool {
+        self.constraint
+            .as_ref()
+            .is_none_or(|constraint| constraint.matches(version))
+    }
+}
+
+impl FromStr for Depend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, constraint) = match s.find(['<', '>', '=']) {
+            Some(index) => {
+                let (name, constraint) = s.split_at(index);
+                let constraint = constraint
+                    .parse::<VersionReq>()
+                    .map_err(|e| e.to_string())?;
+
+                (name, Some(constraint))
+            }
+            None => (s, None),
+        };
+
+        let name = name.trim();
+
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("{} isn't a package name", name));
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            constraint,
+        })
+    }
+}
+
+impl Display for Depend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.constraint {
+            Some(constraint) => write!(f, "{}{}", self.name, constraint),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// A dependency that only applies while a feature is enabled (see [enabled_features]),
+/// written as `<feature>: <depend>` within the depends file, e.g. `x11: libxcb>=1.15`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionalDepend {
+    pub feature: String,
+    pub depend: Depend,
+}
+
 #[derive(Debug, Clone)]
 pub struct Package {
-    pub version: String,
+    pub version: Version,
     pub name: String,
-    pub depends: Vec<String>,
+    pub depends: Vec<Depend>,
+    /// Every conditional dependency, whether its feature is enabled or not.
+    /// Those of the enabled features are part of the [Package::depends] as well.
+    pub conditional_depends: Vec<ConditionalDepend>,
+    /// Other names the package can be referred to by, e.g. `node` for `nodejs`.
+    /// These are read from the optional `aliases` file, one per line.
+    pub aliases: Vec<String>,
+    /// Whether this is a meta-package, which doesn't ship any files by itself.
+    /// This is set by a `meta` file within the package directory.
+    pub meta: bool,
     structure: InstallFileStructure,
     dir: PathBuf,
+    /// The git ref the definition is pinned to, see [git::read_pin].
+    pin: Option<String>,
+    /// The URL of a prebuilt artifact of the package, see [Package::build_binary].
+    binary: Option<String>,
 }
 
 impl Package {
+    /// The directory the package is defined in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
     pub fn is_installed(&self) -> Option<InstallData> {
-        let path = PathBuf::from("/var/db/installed/");
-
-        if !path.exists() {
-            fs::create_dir_all(&path).expect("Couldn't create /var/db/installed/");
-        }
-
-        let dir = fs::read_dir(&path);
-
-        match dir {
-            Ok(value) => {
-                let first = value
-                    .into_iter()
-                    .filter(|r| r.is_ok())
-                    .map(|r| r.unwrap().path())
-                    .find(|r| {
-                        let lossy_str = r.as_os_str().to_string_lossy();
-                        let split = lossy_str.split("/");
-                        let name = split.last().or_else(|| Some("")).unwrap().to_owned();
-
-                        if !r
-                            .read_dir()
-                            .expect("Failed to read directory.")
-                            .flatten()
-                            .any(|f| {
-                                f.file_name()
-                                    .as_os_str()
-                                    .to_string_lossy()
-                                    .ends_with("installed")
-                            })
-                        {
-                            return false;
-                        }
-
-                        name == self.name.clone()
-                    });
-
-                match first {
-                    Some(value) => InstallData::try_from(value).ok(),
-                    None => None,
-                }
-            }
-            Err(_) => None,
+        if !with_index(|index| index.is_installed(&self.name)) {
+            return None;
         }
+
+        InstallData::try_from(db_root().join(&self.name)).ok()
     }
 
     // This method is exactly the same as [is_installed()], however
     // this skips the check for the `installed` file within the directory.
     //
-    // For this reason, this method is also more efficient than the before-mentioned
-    // alternative method.
-    //
     // This is because our current file structure allows you to have non-installed but built
     // packages within the /var/db/installed/ directory. These should probably be re-categorized
     // into something like /var/db/built/, and after installation moved into /var/db/installed. But
     // for now, our structure is like this.
     pub fn is_built(&self) -> Option<InstallData> {
-        let path = PathBuf::from("/var/db/installed/");
+        if !with_index(|index| index.is_built(&self.name)) {
+            return None;
+        }
+
+        InstallData::try_from(db_root().join(&self.name)).ok()
+    }
+
+    /// Whether the package is held, i.e. its entry within the install database contains
+    /// a `hold` file (`touch /var/db/installed/<name>/hold`). Held packages are kept around
+    /// even once nothing depends on them anymore.
+    pub fn is_held(&self) -> bool {
+        db_root().join(&self.name).join("hold").exists()
+    }
+
+    /// The URL of the prebuilt artifact of the package, if it provides one.
+    pub fn binary(&self) -> Option<&str> {
+        self.binary.as_deref()
+    }
+
+    /// Reads a file of the package definition, at the pinned ref for pinned packages.
+    fn read_definition(&self, file: &str) -> std::io::Result<String> {
+        match &self.pin {
+            Some(pin) => git::show(&self.dir, pin, file),
+            None => fs::read_to_string(self.dir.join(file)),
+        }
+    }
+
+    /// The sources of the package, one for each non-empty line of its `sources` file.
+    ///
+    /// The `${name}` and `${version}` placeholders are expanded, so a source like
+    /// `https://example.org/foo-${version}.tar.gz` doesn't have to change with every version.
+    pub fn sources(&self) -> Result<Vec<String>, ParseError> {
+        self.read_definition("sources")
+            .unwrap_or_default()
+            .lines()
+            .enumerate()
+            .filter(|(_, content)| !content.trim().is_empty())
+            .map(|(i, content)| {
+                expand_source(content.trim(), &self.name, self.version.as_str()).map_err(|reason| {
+                    ParseError::InvalidSource {
+                        line: i + 1,
+                        reason,
+                    }
+                })
+            })
+            .collect()
+    }
 
-        if !path.exists() {
-            fs::create_dir_all(&path).expect("Couldn't create /var/db/installed/");
+    // Fetches every source of the package, returning the file name of every source together with
+    // its path. Sources with a URL are downloaded into a fresh `download_dir`, any other source is
+    // a file relative to `definition`. Either way, the file is the last component of the source.
+    fn fetch_sources(
+        &self,
+        definition: &Path,
+        download_dir: &Path,
+    ) -> Result<Vec<(String, PathBuf)>, ParseError> {
+        if download_dir.exists() {
+            fs::remove_dir_all(download_dir)?;
         }
 
-        let dir = fs::read_dir(&path);
+        let mut fetched = Vec::<(String, PathBuf)>::new();
 
-        match dir {
-            Ok(value) => {
-                let first = value
-                    .into_iter()
-                    .filter(|r| r.is_ok())
-                    .map(|r| r.unwrap().path())
-                    .find(|r| {
-                        let lossy_str = r.as_os_str().to_string_lossy();
-                        let split = lossy_str.split("/");
-                        let name = split.last().or_else(|| Some("")).unwrap().to_owned();
+        for source in self.sources()? {
+            let file = source.rsplit('/').next().unwrap_or(&source).to_owned();
 
-                        name == self.name.clone()
-                    });
+            let path = match source.contains("://") {
+                true => {
+                    fs::create_dir_all(download_dir)?;
 
-                match first {
-                    Some(value) => InstallData::try_from(value).ok(),
-                    None => None,
+                    let path = download_dir.join(&file);
+                    fetch::download(&source, &path, fetch::reporter(&source))?;
+                    path
                 }
+                false => definition.join(&source),
+            };
+
+            fetched.push((file, path));
+        }
+
+        Ok(fetched)
+    }
+
+    // Verifies every source of the package against its `checksums` file before it's built, after
+    // downloading them into `download_dir`, where the install script finds them through
+    // `PUR_SOURCES_DIR`. Packages without a `checksums` file aren't verified.
+    fn verify_sources(&self, definition: &Path, download_dir: &Path) -> Result<(), ParseError> {
+        let checksums = match self.read_definition("checksums") {
+            Ok(checksums) => checksums,
+            Err(_) => return Ok(()),
+        };
+
+        let (files, paths): (Vec<String>, Vec<PathBuf>) = self
+            .fetch_sources(definition, download_dir)?
+            .into_iter()
+            .unzip();
+
+        for (file, got) in files.into_iter().zip(checksum::sha256_files(&paths)) {
+            let expected = expected_checksum(&checksums, &file).ok_or_else(|| {
+                ParseError::Other(format!(
+                    "{} doesn't have a checksum for {}",
+                    self.name, file
+                ))
+            })?;
+            let got = got?;
+
+            if got != expected {
+                return Err(ParseError::ChecksumMismatch {
+                    file,
+                    expected,
+                    got,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The git ref the package definition is pinned to, if any.
+    pub fn pin(&self) -> Option<&str> {
+        self.pin.as_deref()
+    }
+
+    /// The version of the current definition, whenever the pin holds the package back from it.
+    pub fn held_back(&self) -> Option<Version> {
+        self.pin.as_ref()?;
+
+        let current = parse_version(&fs::read_to_string(self.dir.join("version")).ok()?).ok()?;
+
+        (current > self.version).then_some(current)
+    }
+
+    /// Whether there's nothing to build for the package, which is the case for meta-packages
+    /// without an install script, or with an empty one. These only pull in their dependencies,
+    /// so installing them only records them as installed.
+    fn is_placeholder(&self, config: &Config, definition: &Path) -> bool {
+        self.meta
+            && self.phases(config, definition).map_or(true, |phases| {
+                phases
+                    .iter()
+                    .all(|phase| fs::metadata(phase).is_ok_and(|meta| meta.len() == 0))
+            })
+    }
+
+    /// The scripts building the package from the given definition, in the order they run.
+    ///
+    /// A package either has a single `install` script, which both compiles the package and
+    /// installs it into the build directory, or splits these into phases: an optional `build`
+    /// script compiling the package, followed by a `package` script installing it. The names
+    /// of these scripts can be configured, see [Config::script_name].
+    fn phases(&self, config: &Config, definition: &Path) -> Result<Vec<PathBuf>, ParseError> {
+        let repo = self.dir.parent().unwrap_or(&self.dir);
+        let script = |key: &str| definition.join(config.script_name(repo, key));
+
+        let build = script("build_script");
+        let package = script("package_script");
+        let install = script("install_script");
+
+        let last = match (package.is_file(), install.is_file()) {
+            (true, _) => package,
+            (false, true) => install,
+            (false, false) => {
+                return Err(ParseError::NoInstallScript {
+                    package: self.name.clone(),
+                    path: install,
+                })
+            }
+        };
+
+        Ok(match build.is_file() {
+            true => vec![build, last],
+            false => vec![last],
+        })
+    }
+
+    /// Checks the package definition for common problems, without building anything.
+    ///
+    /// The given packages are the ones the dependencies of this package are resolved against.
+    pub fn validate(&self, packages: &[Package]) -> Vec<ValidationWarning> {
+        let mut warnings = Vec::<ValidationWarning>::new();
+        let config = Config::load();
+
+        match self.phases(&config, &self.dir) {
+            _ if self.is_placeholder(&config, &self.dir) => {}
+            Err(_) => warnings.push(ValidationWarning::NoInstallScript),
+            Ok(phases) if !phases.iter().all(|phase| is_executable(phase)) => {
+                warnings.push(ValidationWarning::InstallScriptNotExecutable)
+            }
+            Ok(_) => {}
+        }
+
+        if self.version.is_empty() {
+            warnings.push(ValidationWarning::EmptyVersion);
+        }
+
+        let mut seen = HashSet::<&String>::new();
+
+        for depend in &self.depends {
+            if !seen.insert(&depend.name) {
+                warnings.push(ValidationWarning::DuplicateDepend(depend.name.clone()));
+            } else if !packages.iter().any(|package| package.name == depend.name) {
+                warnings.push(ValidationWarning::MissingDepend(depend.name.clone()));
+            }
+        }
+
+        // conditional dependencies have to exist too, whether their feature is enabled right now or not.
+        for ConditionalDepend { depend, .. } in &self.conditional_depends {
+            if !seen.contains(&depend.name)
+                && !packages.iter().any(|package| package.name == depend.name)
+            {
+                warnings.push(ValidationWarning::MissingDepend(depend.name.clone()));
+            }
+        }
+
+        // these files are optional, so we only check them whenever they're present.
+        for (line, content) in read_lines(&self.dir.join("checksums")) {
+            let mut fields = content.split_whitespace();
+
+            let valid = matches!(
+                (fields.next(), fields.next(), fields.next()),
+                (Some(sum), Some(_), None)
+                    if sum.len() == 64 && sum.chars().all(|c| c.is_ascii_hexdigit())
+            );
+
+            if !valid {
+                warnings.push(ValidationWarning::MalformedChecksum { line, content });
+            }
+        }
+
+        for (line, content) in read_lines(&self.dir.join("sources")) {
+            if content.split_whitespace().count() != 1
+                || expand_source(&content, &self.name, self.version.as_str()).is_err()
+            {
+                warnings.push(ValidationWarning::MalformedSource { line, content });
             }
-            Err(_) => None,
         }
+
+        warnings
     }
 
+    /// Rebuilds and reinstalls the package, e.g. after a new version has been pulled in.
+    ///
+    /// The links of files the new build doesn't ship anymore are removed, based on the manifest of
+    /// the previous installation. The other links are kept, as the files keep their paths.
     pub fn update(&self) -> Result<(), ParseError> {
-        self.remove_binaries()?;
+        // packages installed before manifests were recorded get theirs reconstructed first.
+        let previous = match self.manifest().map(Ok).unwrap_or_else(|| self.reindex()) {
+            Ok(manifest) => manifest,
+            // without a manifest, we can't tell which links are ours, so they all have to go.
+            Err(_) => {
+                self.remove_binaries()?;
+                Manifest::default()
+            }
+        };
+
         self.build()?;
+
+        let root = install_root();
+        let links = self
+            .structure
+            .links(&root)
+            .map_err(|e| ParseError::Other(e.to_string()))?
+            .into_iter()
+            .map(|(_, target)| target)
+            .collect::<Vec<PathBuf>>();
+
+        let stale = stale_links(&previous.resolve(&root), &links);
+        linker::record(&root, &stale);
+
+        for path in stale {
+            let _ = fs::remove_file(path);
+        }
+
         self.install()?;
 
         Ok(())
     }
 
+    /// Builds the package by running its install script.
+    ///
+    /// The script runs within a scratch directory of its own within the [build_dir], which it
+    /// installs into. Only the resulting structure children are moved into the install database
+    /// afterwards, everything else is left within the build directory until the next build
+    /// (or `pur clean --build`).
     pub fn build(&self) -> Result<(), ParseError> {
-        let installed_dir = PathBuf::from(format!("/var/db/installed/{}", self.name));
+        ensure_db_writable()?;
+
+        let installed_dir = db_root().join(&self.name);
         let files_dir = installed_dir.join("files");
 
         self.structure
             .create_all()
             .map_err(|e| ParseError::Other(e.to_string()))?;
+        invalidate_index();
+
+        let started = Instant::now();
+        let profile = self.build_files(
+            &Config::load(),
+            &installed_dir.join("build.log"),
+            &files_dir,
+        )?;
 
-        // the version data
-        let bytes = format!("{}", self.version).as_bytes().to_owned();
+        // the version data, which is only written once the build succeeded, so a package
+        // of which the install script failed isn't considered built, see [InstalledIndex::scan].
+        // A previous build that's still installed keeps its version and files.
+        let bytes = self.version.as_str().as_bytes().to_owned();
         let version_file = installed_dir.join("version");
 
         if version_file.exists() {
@@ -136,37 +569,448 @@ impl Package {
 
         let mut file = File::create(&version_file)?;
         file.write_all(&bytes)?;
+        self.record_depends(&installed_dir)?;
+
+        // how the package was built, see [Package::build_info].
+        fs::write(
+            installed_dir.join("build-info"),
+            format!(
+                "profile = {}\nbuild_time = {:.2}s\n",
+                profile,
+                started.elapsed().as_secs_f64()
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    /// Installs the prebuilt artifact of the package into the install database instead of building it,
+    /// e.g. whenever building it from source failed.
+    ///
+    /// The artifact is given by the URL within the optional `binary` file of the package. It's a tarball
+    /// laid out like the files directory (`usr/bin/...`), and has to match its checksum within the
+    /// `checksums` file like any source.
+    pub fn build_binary(&self) -> Result<(), ParseError> {
+        let url = self.binary.as_deref().ok_or_else(|| {
+            ParseError::Other(format!("{} doesn't provide a prebuilt binary", self.name))
+        })?;
+
+        ensure_db_writable()?;
+
+        let installed_dir = db_root().join(&self.name);
+        let download_dir = build_dir().join(format!("{}.binary", self.name));
+        let file_name = url.rsplit('/').next().unwrap_or(url);
+        let archive = download_dir.join(file_name);
+
+        if download_dir.exists() {
+            fs::remove_dir_all(&download_dir)?;
+        }
+
+        fs::create_dir_all(&download_dir)?;
+        fetch::download(url, &archive, fetch::reporter(url))?;
+
+        let checksums = self.read_definition("checksums").unwrap_or_default();
+
+        let expected = expected_checksum(&checksums, file_name).ok_or_else(|| {
+            ParseError::Other(format!(
+                "{} doesn't have a checksum for {}",
+                self.name, file_name
+            ))
+        })?;
+
+        let got = checksum::sha256_file(&archive)?;
+
+        if got != expected {
+            return Err(ParseError::ChecksumMismatch {
+                file: file_name.to_owned(),
+                expected: expected.to_owned(),
+                got,
+            });
+        }
+
+        let extracted = download_dir.join("files");
+        fetch::extract(&archive, &extracted)?;
+
+        self.structure
+            .create_all()
+            .map_err(|e| ParseError::Other(e.to_string()))?;
+        invalidate_index();
 
-        // actually change the directory
-        set_current_dir(&files_dir.as_os_str())?;
+        fs::write(installed_dir.join("version"), self.version.as_str())?;
+        self.record_depends(&installed_dir)?;
 
-        let install_script = self.dir.join("install");
+        self.structure
+            .in_dir(&extracted)
+            .move_all(&installed_dir.join("files"))
+            .map_err(|e| ParseError::Other(e.to_string()))?;
 
-        // We're invoking the install script as a command here.
-        Command::new(install_script.as_os_str())
-            .args([&files_dir, &self.dir])
-            .spawn()
-            .map_err(|_| ParseError::NoInstallScript)?
-            .wait_with_output()
-            .map_err(|_| ParseError::FailedInstallScript)?;
+        // how the package was built, see [Package::build_info].
+        fs::write(
+            installed_dir.join("build-info"),
+            format!("binary = {}\n", url),
+        )?;
 
         Ok(())
     }
 
+    // Records the names of the dependencies the package is built with, so the packages
+    // depending on it are known even once they're gone from the repositories, see [dependents].
+    fn record_depends(&self, installed_dir: &Path) -> io::Result<()> {
+        let names = self
+            .depends
+            .iter()
+            .map(|depend| format!("{}\n", depend.name))
+            .collect::<String>();
+
+        fs::write(installed_dir.join("depends"), names)
+    }
+
+    /// Builds the package into the given directory, with the files laid out like they are
+    /// within the install database, without touching the install database at all.
+    ///
+    /// The build log is kept next to the build directory instead.
+    pub fn build_to(&self, output: &Path) -> Result<(), ParseError> {
+        self.build_to_with(&Config::load(), output)
+    }
+
+    fn build_to_with(&self, config: &Config, output: &Path) -> Result<(), ParseError> {
+        let log = Path::new(config.value("build_dir")).join(format!("{}.log", self.name));
+
+        self.build_files(config, &log, output).map(|_| ())
+    }
+
+    /// Runs the install script in a scratch directory, and moves the files it produced into `target`.
+    ///
+    /// Every phase gets the scratch directory and the package definition as its arguments,
+    /// and the package is described by these variables as well:
+    ///
+    /// - `PUR_PKG_NAME`: the name of the package
+    /// - `PUR_PKG_VERSION`: the version that's being built, e.g. for download URLs
+    /// - `PUR_FILES_DIR`: the scratch directory the files are installed into, i.e. `$1`
+    /// - `PUR_PKG_DIR`: the directory of the package definition, i.e. `$2`
+    /// - `PUR_SOURCES_DIR`: where the sources with a URL were downloaded into while verifying
+    ///   them against the `checksums` file, which doesn't exist for packages without one
+    ///
+    /// Returns the build profile that was used.
+    fn build_files(
+        &self,
+        config: &Config,
+        log: &Path,
+        target: &Path,
+    ) -> Result<String, ParseError> {
+        let build_dir = Path::new(config.value("build_dir"));
+        let scratch_dir = build_dir.join(&self.name);
+
+        // A pinned package is built from its definition at the pinned ref,
+        // whatever the repository currently contains.
+        let definition = match &self.pin {
+            Some(pin) => {
+                let definition = build_dir.join(format!("{}.definition", self.name));
+
+                if definition.exists() {
+                    fs::remove_dir_all(&definition)?;
+                }
+
+                git::export(&self.dir, pin, &definition)?;
+                definition
+            }
+            None => self.dir.clone(),
+        };
+
+        let profile = config.value("profile").to_owned();
+
+        if self.is_placeholder(config, &definition) {
+            return Ok(profile);
+        }
+
+        // We want to check the scripts before running them, so we can tell
+        // the user exactly what's wrong with them instead of a generic spawn error.
+        let phases = self.phases(config, &definition)?;
+
+        // the sources are verified before anything of them runs.
+        let sources_dir = sources_dir(build_dir, &self.name);
+        self.verify_sources(&definition, &sources_dir)?;
+
+        // every build starts from scratch, so leftovers of a previous build can't end up in this one.
+        if scratch_dir.exists() {
+            fs::remove_dir_all(&scratch_dir)?;
+        }
+
+        let scratch = self.structure.in_dir(&scratch_dir);
+
+        for path in scratch.get_children().into_iter().map(|(path, _)| path) {
+            fs::create_dir_all(path)?;
+        }
+
+        // Scripts should reference $PUR_PREFIX (e.g. for ./configure --prefix) rather
+        // than hardcoding /usr, since that's where the structure links files out of.
+        // The package ends up linked under $PUR_ROOT (see --root) rather than the build directory,
+        // which scripts baking absolute paths into the package have to know about.
+        let prefix = config.value("prefix");
+        let root = config.install_root();
+        let verbose = config.flag("verbose_scripts");
+        let (clean, env) = build_env(&definition, config.flag("clean_env"));
+
+        // The profile is chosen by the user, so its variables take precedence over the package's.
+        let profile_env = config.profile_env();
+
+        // Every phase gets the same arguments and environment, and runs within the scratch
+        // directory. That's only the directory of the script rather than ours, as the current
+        // directory is shared by every build running at the same time, see --jobs.
+        let spawn = |script: &Path, shell: bool| {
+            let mut command = script::command(script, shell, verbose);
+
+            if clean {
+                script::sanitize(&mut command);
+            }
+
+            command
+                .current_dir(&scratch_dir)
+                .args([&scratch_dir, &definition])
+                .envs(&env)
+                .envs(&profile_env)
+                .env("PUR_PREFIX", prefix)
+                .env("PUR_ROOT", &root)
+                .env("PUR_PROFILE", &profile)
+                .env("PUR_PKG_NAME", &self.name)
+                .env("PUR_PKG_VERSION", self.version.to_string())
+                .env("PUR_FILES_DIR", &scratch_dir)
+                .env("PUR_PKG_DIR", &definition)
+                .env("PUR_SOURCES_DIR", &sources_dir)
+                .spawn()
+        };
+
+        // With --audit-scripts, we look for files the script touched outside of its build directory,
+        // within the audited directories of the install root. The install database is left out, as
+        // that's where we write ourselves.
+        let audit = config.flag("audit_scripts").then(|| {
+            let dirs = config
+                .value("audit_dirs")
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(|dir| root.join(dir.trim_start_matches('/')))
+                .collect::<Vec<PathBuf>>();
+            let exclude = vec![scratch_dir.clone(), PathBuf::from(config.value("db_root"))];
+            let snapshot = Snapshot::take(&dirs, &exclude);
+
+            (dirs, exclude, snapshot)
+        });
+
+        // The output of the scripts is kept within the build log, which includes the trace
+        // of every command when --verbose-scripts is set. It's only shown live with --verbose,
+        // otherwise the last lines of stderr end up in the error whenever a script fails.
+        // The logs of previous builds are rotated, so every build gets a fresh log.
+        let keep = config.value("build_logs").parse::<usize>().unwrap_or(5);
+
+        script::rotate_logs(log, keep)?;
+
+        for phase in &phases {
+            // We're invoking the script as a command here.
+            let child = match spawn(phase, false) {
+                Ok(child) => child,
+                // Scripts without an execute bit or shebang can't be executed directly,
+                // but we can still run them through the shell like other source managers do.
+                Err(e) if script::is_exec_error(&e) => {
+                    warn(format!(
+                        "script {} is not executable, falling back to /bin/sh",
+                        phase.display()
+                    ));
+
+                    spawn(phase, true).map_err(|_| ParseError::InstallScriptNotExecutable {
+                        package: self.name.clone(),
+                        path: phase.clone(),
+                    })?
+                }
+                Err(e) => {
+                    return Err(ParseError::Other(format!(
+                        "Couldn't run script {} of {}: {}",
+                        phase.display(),
+                        self.name,
+                        e
+                    )))
+                }
+            };
+
+            let (status, tail) = script::run_logged(child, log, config.flag("verbose"))
+                .map_err(|e| ParseError::FailedInstallScript(vec![e.to_string()]))?;
+
+            // there's nothing to package whenever the build phase failed.
+            if !status.success() {
+                return Err(ParseError::FailedInstallScript(tail));
+            }
+        }
+
+        if let Some((dirs, exclude, before)) = audit {
+            let changes = before.changes(&Snapshot::take(&dirs, &exclude));
+
+            if !changes.is_empty() {
+                warn(format!(
+                    "the install script of {} touched {} file(s) outside of its build directory",
+                    self.name,
+                    changes.len()
+                ));
+
+                for path in changes {
+                    println!("  {}", path.display());
+                }
+            }
+        }
+
+        scratch
+            .move_all(target)
+            .map_err(|e| ParseError::Other(e.to_string()))?;
+
+        Ok(profile)
+    }
+
+    /// How the package was built, e.g. the `profile`, as `key = value` pairs.
+    ///
+    /// Packages built before this was recorded don't have any.
+    pub fn build_info(&self) -> Option<HashMap<String, String>> {
+        let installed_dir = db_root().join(&self.name);
+
+        read_config_file(&installed_dir.join("build-info")).ok()
+    }
+
     pub fn install(&self) -> Result<(), BuildError> {
-        let installed_dir = PathBuf::from(format!("/var/db/installed/{}", self.name));
-        let _ = File::create(installed_dir.join("installed"));
+        ensure_db_writable()?;
 
-        self.structure
+        let started = Instant::now();
+        let installed_dir = db_root().join(&self.name);
+
+        // the files of other packages are never linked over, they have to be
+        // gotten out of the way first, see [clear_conflicts].
+        if let Some(conflict) = self
+            .conflicts()?
+            .into_iter()
+            .find(|conflict| conflict.owner.is_some())
+        {
+            return Err(BuildError::FileConflict {
+                path: conflict.path,
+                owner: conflict.owner.unwrap_or_default(),
+            });
+        }
+
+        // a package of which not every link could be created isn't linked at all,
+        // and it's only marked as installed once it is.
+        let links = self
+            .structure
             .symlink_out_scope()
-            .map_err(|_| BuildError::LinkError)
+            .map_err(|_| BuildError::LinkError)?;
+
+        // a package is only installed once its marker is written, so its links are taken back
+        // whenever that fails, like whenever not every link could be created.
+        let unlink = |links: &[PathBuf]| {
+            for link in links {
+                let _ = fs::remove_file(link);
+            }
+        };
+
+        // the marker records when the package was installed, see [InstallData::installed_at].
+        if let Err(e) = fs::write(installed_dir.join("installed"), install_timestamp()) {
+            unlink(&links);
+
+            return Err(BuildError::ReadOnlyDatabase(format!(
+                "couldn't mark {} as installed: {}",
+                self.name, e
+            )));
+        }
+
+        invalidate_index();
+
+        // A package without any files is fine if it's a meta-package, we record it as such
+        // so it's clear the empty manifest is deliberate. Otherwise the build most likely
+        // didn't install into the directory it was given.
+        if links.is_empty() {
+            if self.meta {
+                File::create(installed_dir.join("meta")).map_err(|_| BuildError::LinkError)?;
+            } else {
+                warn(format!(
+                    "{} v{} produced no files, is its install script installing into the directory it gets as first argument?",
+                    self.name, self.version
+                ));
+            }
+        }
+
+        linker::record(&install_root(), &links);
+
+        // The manifest is stored relative to the install root, so it stays valid
+        // even if the root this was installed under gets mounted somewhere else.
+        if Manifest::from_paths(&install_root(), &links)
+            .store(&installed_dir)
+            .is_err()
+        {
+            unlink(&links);
+            let _ = fs::remove_file(installed_dir.join("installed"));
+            invalidate_index();
+
+            return Err(BuildError::LinkError);
+        }
+
+        // the install went fine whether or not its duration could be recorded.
+        let _ = set_build_info(
+            &installed_dir,
+            "install_time",
+            &format!("{:.2}s", started.elapsed().as_secs_f64()),
+        );
+
+        Ok(())
+    }
+
+    /// Finds every file that's in the way of the links this package creates during installation,
+    /// checked against the manifests of all other installed packages, so they can all be
+    /// reported at once before anything gets linked.
+    ///
+    /// This requires the package to be built.
+    pub fn conflicts(&self) -> Result<Vec<Conflict>, BuildError> {
+        conflicts_in(&db_root(), &install_root(), &self.name, &self.structure)
+    }
+
+    /// Reads the manifest of the files this package linked out during installation.
+    ///
+    /// Packages installed before manifests were recorded don't have one.
+    pub fn manifest(&self) -> Option<Manifest> {
+        let installed_dir = db_root().join(&self.name);
+
+        Manifest::load(&installed_dir).ok()
+    }
+
+    /// Reconstructs and stores the manifest of a package that was installed before manifests were
+    /// recorded, out of the links within the install root which point into its files.
+    ///
+    /// Links that point anywhere else aren't recorded, so files of other packages aren't claimed.
+    pub fn reindex(&self) -> Result<Manifest, ParseError> {
+        if self.is_installed().is_none() {
+            return Err(ParseError::NotInstalled);
+        }
+
+        ensure_db_writable()?;
+
+        let installed_dir = db_root().join(&self.name);
+        let manifest = manifest_from_links(&install_root(), &self.structure)?;
+
+        manifest.store(&installed_dir)?;
+
+        Ok(manifest)
     }
 
     pub fn uninstall(&self) -> Result<(), ParseError> {
-        if self.is_built().is_none() {
+        let installed_dir = db_root().join(&self.name);
+        let removing = installed_dir.join("removing");
+
+        // a package of which the removal was interrupted isn't built anymore,
+        // but we still want to be able to finish removing it.
+        if self.is_built().is_none() && !removing.exists() {
             return Err(ParseError::NotInstalled);
         }
 
+        ensure_db_writable()?;
+
+        // This marker is removed together with the rest of the directory, so whenever it's
+        // still there, we know the removal got interrupted somewhere, see [interrupted_removals].
+        File::create(&removing)?;
+        invalidate_index();
+
         // first, we want to remove the binaries.
         // these binaries are stored within the `installed_dir` directory,
         // so we have to delete them before we delete the directory.
@@ -180,12 +1024,47 @@ impl Package {
         Ok(())
     }
 
-    pub fn remove_binaries(&self) -> Result<(), ParseError> {
+    /// Every link installing the package creates within the install root, together with the file
+    /// it points to, without creating anything. This requires the package to be built.
+    pub fn links(&self) -> Result<Vec<(PathBuf, PathBuf)>, BuildError> {
         self.structure
-            .remove_symlinks()
-            .map_err(|e| ParseError::NoDirectory(e.to_string()))?;
+            .links(&install_root())
+            .map_err(|_| BuildError::LinkError)
+    }
 
-        Ok(())
+    /// Every path that's deleted when the package is removed, without deleting anything.
+    ///
+    /// These are the links from the manifest (or the file structure, for packages without one),
+    /// followed by the directories of the package within the install database.
+    pub fn removal_paths(&self) -> Result<Vec<PathBuf>, ParseError> {
+        let installed_dir = db_root().join(&self.name);
+        let root = install_root();
+
+        let mut paths = match Manifest::load(&installed_dir) {
+            Ok(manifest) => manifest.resolve(&root),
+            Err(_) => self
+                .structure
+                .links(&root)
+                .map_err(|e| ParseError::NoDirectory(e.to_string()))?
+                .into_iter()
+                .map(|(_, target)| target)
+                .collect(),
+        };
+
+        paths.extend(
+            self.structure
+                .deletion_order()
+                .into_iter()
+                .filter(|path| path.exists()),
+        );
+
+        Ok(paths)
+    }
+
+    pub fn remove_binaries(&self) -> Result<(), ParseError> {
+        let installed_dir = db_root().join(&self.name);
+
+        remove_links(&installed_dir, &install_root(), &self.structure)
     }
 }
 
@@ -193,34 +1072,1439 @@ impl TryFrom<PathBuf> for Package {
     type Error = ParseError;
 
     fn try_from(value: PathBuf) -> Result<Self, Self::Error> {
-        let dir = value;
+        // the definition of a pinned package is read as it was at the pinned ref.
+        Self::try_from(Definition::read(&value))
+    }
+}
 
-        let name: String = dir
-            .file_name()
-            .map(|name| name.to_string_lossy().into_owned())
-            .unwrap_or("".into());
+// The definition files may come from an index, see [crate::index::load].
+impl TryFrom<Definition> for Package {
+    type Error = ParseError;
 
-        let version = fs::read_to_string(dir.join("version"))
-            .map_err(|_| ParseError::NoVersion)?
-            .chars()
-            .filter(|x| !x.is_whitespace())
-            .collect::<String>();
+    fn try_from(definition: Definition) -> Result<Self, Self::Error> {
+        let read = |file: &str| definition.file(file);
+        let dir = definition.dir.clone();
+        let pin = definition.pin.clone();
 
-        let depends = fs::read_to_string(dir.join("depends"))
-            .map_err(|_| ParseError::NoDepends)?
-            .lines()
+        // a lossy name would point to another directory within the install database.
+        let name = match dir.file_name().map(|name| name.to_str()) {
+            Some(Some(name)) => name.to_owned(),
+            Some(None) => return Err(ParseError::InvalidName(dir)),
+            None => String::new(),
+        };
+
+        let version = parse_version(&read("version").map_err(|_| ParseError::NoVersion)?)?;
+
+        let (depends, conditional_depends) = parse_depends(
+            &read("depends").map_err(|_| ParseError::NoDepends)?,
+            &enabled_features(),
+        )?;
+
+        let aliases = definition_lines(&read("aliases").unwrap_or_default())
             .map(String::from)
-            .filter(|x| !x.is_empty())
             .collect::<Vec<String>>();
 
-        let structure = InstallFileStructure::new(&name);
+        let meta = read("meta").is_ok();
+
+        // the directories which are linked as a whole, instead of file by file.
+        let link_dirs = definition_lines(&read("linkdirs").unwrap_or_default())
+            .map(PathBuf::from)
+            .collect::<Vec<PathBuf>>();
+
+        // the directories that are managed on top of the default ones, e.g. `usr/share` or `etc`.
+        let paths = definition_lines(&read("paths").unwrap_or_default())
+            .map(PathBuf::from)
+            .collect::<Vec<PathBuf>>();
+
+        let structure = InstallFileStructure::new(&name)
+            .with_paths(&paths)
+            .with_link_dirs(&link_dirs);
+
+        let binary = read("binary")
+            .ok()
+            .and_then(|content| definition_lines(&content).next().map(String::from));
 
         Ok(Self {
             version,
             dir,
             name,
             depends,
+            conditional_depends,
+            aliases,
+            meta,
             structure,
+            pin,
+            binary,
         })
     }
 }
+
+/// The lines of a file of a package definition which aren't blank, with their comments stripped.
+///
+/// Everything after a `#` is a comment, e.g. `usr/share # for the man pages`.
+pub fn definition_lines(content: &str) -> impl Iterator<Item = &str> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+}
+
+// Parses a depends file into the dependencies that apply with the given features enabled,
+// and every conditional dependency.
+//
+// Everything after a `#` is a comment, e.g. `openssl # for TLS`.
+fn parse_depends(
+    content: &str,
+    features: &[String],
+) -> Result<(Vec<Depend>, Vec<ConditionalDepend>), ParseError> {
+    let mut depends = Vec::new();
+    let mut conditional = Vec::new();
+
+    for (index, line) in content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .enumerate()
+        .filter(|(_, x)| !x.is_empty())
+    {
+        let parse = |depend: &str| {
+            depend
+                .parse::<Depend>()
+                .map_err(|reason| ParseError::InvalidDepend {
+                    line: index + 1,
+                    reason,
+                })
+        };
+
+        // the colon of an epoch within a constraint doesn't start a conditional dependency.
+        match line
+            .split_once(':')
+            .filter(|(feature, _)| !feature.contains(['<', '>', '=']))
+        {
+            Some((feature, depend)) => {
+                let depend = ConditionalDepend {
+                    feature: feature.trim().to_owned(),
+                    depend: parse(depend)?,
+                };
+
+                if features.contains(&depend.feature) {
+                    depends.push(depend.depend.clone());
+                }
+
+                conditional.push(depend);
+            }
+            None => depends.push(parse(line)?),
+        }
+    }
+
+    Ok((depends, conditional))
+}
+
+// The manifest of the links within the root which point at the files of the structure.
+fn manifest_from_links(
+    root: &Path,
+    structure: &InstallFileStructure,
+) -> Result<Manifest, ParseError> {
+    let links = structure
+        .links(root)
+        .map_err(|e| ParseError::Other(e.to_string()))?
+        .into_iter()
+        .filter(|(file, target)| fs::read_link(target).ok().as_ref() == Some(file))
+        .map(|(_, target)| target)
+        .collect::<Vec<PathBuf>>();
+
+    Ok(Manifest::from_paths(root, &links))
+}
+
+// The contents of the `installed` marker, the seconds since the unix epoch.
+fn install_timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+
+    format!("{}\n", secs)
+}
+
+// Sets a line of the build-info file within the given install database entry,
+// replacing the line of the same key, see [Package::build_info].
+fn set_build_info(installed_dir: &Path, key: &str, value: &str) -> std::io::Result<()> {
+    let path = installed_dir.join("build-info");
+    let mut content = fs::read_to_string(&path)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| line.split_once('=').map(|(k, _)| k.trim()) != Some(key))
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+
+    content.push_str(&format!("{} = {}\n", key, value));
+    fs::write(path, content)
+}
+
+// Expands the `${name}` and `${version}` placeholders within a line of the sources file.
+fn expand_source(content: &str, name: &str, version: &str) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 2..];
+
+        let end = rest
+            .find('}')
+            .ok_or_else(|| format!("unterminated placeholder in {}", content))?;
+
+        match &rest[..end] {
+            "name" => expanded.push_str(name),
+            "version" => expanded.push_str(version),
+            variable => {
+                return Err(format!(
+                    "unknown variable ${{{}}} in {}, only ${{name}} and ${{version}} are known",
+                    variable, content
+                ))
+            }
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// The environment of the install script, read from the optional `env` file within the
+/// package definition, consisting of `KEY=VALUE` lines which are exported to the script.
+///
+/// Returns whether the script should run within a sanitized environment, which defaults to
+/// the `clean_env` setting, but can be overridden with `PUR_CLEAN_ENV` within the `env` file.
+fn build_env(definition: &Path, clean: bool) -> (bool, HashMap<String, String>) {
+    let mut env = read_config_file(&definition.join("env")).unwrap_or_default();
+
+    let clean = match env.remove("PUR_CLEAN_ENV") {
+        Some(value) => is_enabled(&value),
+        None => clean,
+    };
+
+    (clean, env)
+}
+
+// The links of a previous installation that aren't part of the new one.
+fn stale_links(previous: &[PathBuf], current: &[PathBuf]) -> Vec<PathBuf> {
+    let current = current.iter().collect::<HashSet<&PathBuf>>();
+
+    previous
+        .iter()
+        .filter(|path| !current.contains(path))
+        .cloned()
+        .collect()
+}
+
+// Removes the links of an installed package, through its manifest if it has one.
+fn remove_links(
+    installed_dir: &Path,
+    root: &Path,
+    structure: &InstallFileStructure,
+) -> Result<(), ParseError> {
+    match Manifest::load(installed_dir) {
+        // the manifest entries are resolved against the current install root.
+        Ok(manifest) => {
+            let paths = manifest.resolve(root);
+            linker::record(root, &paths);
+
+            remove_links_into(&installed_dir.join("files"), &paths)
+                .map_err(|e| ParseError::NoDirectory(e.to_string()))?
+        }
+        // without a manifest, we have to figure out the symlinks from the file structure.
+        Err(_) => {
+            if let Ok(links) = structure.links(root) {
+                let paths = links.into_iter().map(|(_, target)| target);
+                linker::record(root, &paths.collect::<Vec<PathBuf>>());
+            }
+
+            structure
+                .remove_symlinks()
+                .map_err(|e| ParseError::NoDirectory(e.to_string()))?
+        }
+    }
+
+    Ok(())
+}
+
+fn conflicts_in(
+    db: &Path,
+    root: &Path,
+    name: &str,
+    structure: &InstallFileStructure,
+) -> Result<Vec<Conflict>, BuildError> {
+    let mut owners = HashMap::<PathBuf, String>::new();
+
+    for entry in fs::read_dir(db).into_iter().flatten().flatten() {
+        let owner = match entry.file_name().into_string() {
+            Ok(owner) => owner,
+            Err(_) => continue,
+        };
+
+        if owner == name || entry.path().join("removing").exists() {
+            continue;
+        }
+
+        if let Ok(manifest) = Manifest::load(&entry.path()) {
+            for path in manifest.resolve(root) {
+                owners.insert(path, owner.clone());
+            }
+        }
+    }
+
+    let links = structure.links(root).map_err(|_| BuildError::LinkError)?;
+
+    // On case-insensitive file systems, the file in the way may only differ in case (`README` and `readme`),
+    // it's reported instead so its owner is known and clearing the conflicts gets rid of the right file.
+    // Collisions between the links of the package itself are left to the installation to report.
+    let collisions = structure
+        .case_collisions(root)
+        .map_err(|_| BuildError::LinkError)?
+        .into_iter()
+        .filter(|(_, other)| other.symlink_metadata().is_ok())
+        .collect::<HashMap<PathBuf, PathBuf>>();
+
+    // packages installed before manifests were recorded are found through their links instead.
+    let owner = |path: &Path| {
+        owners
+            .get(path)
+            .cloned()
+            .or_else(|| owner_in(db, path).filter(|owner| owner != name))
+    };
+
+    Ok(links
+        .into_iter()
+        .filter_map(|(file, path)| match owner(&path) {
+            _ if collisions.contains_key(&path) => Some(Conflict {
+                owner: owner(&collisions[&path]),
+                path: collisions[&path].clone(),
+            }),
+            Some(owner) => Some(Conflict {
+                owner: Some(owner),
+                path,
+            }),
+            // our own links from a previous installation aren't in the way.
+            None if path.symlink_metadata().is_ok() && fs::read_link(&path).ok() != Some(file) => {
+                Some(Conflict { owner: None, path })
+            }
+            None => None,
+        })
+        .collect())
+}
+
+/// The installed package a path within the install root belongs to, found by reading the link
+/// at that path, which points into the files of its package within the install database.
+///
+/// Anything that isn't such a link, e.g. a regular file, doesn't belong to any package.
+pub fn owner_of(path: &Path) -> Option<String> {
+    owner_in(&db_root(), path)
+}
+
+fn owner_in(db: &Path, path: &Path) -> Option<String> {
+    let file = fs::read_link(path).ok()?;
+    let mut components = file
+        .strip_prefix(std::path::absolute(db).ok()?)
+        .ok()?
+        .components();
+
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(name)), Some(Component::Normal(files))) if files == "files" => {
+            name.to_str().map(String::from)
+        }
+        _ => None,
+    }
+}
+
+/// Gets the conflicting files out of the way, see [Package::conflicts].
+///
+/// With `backup` set, they're renamed to `<file>.pur-backup` instead of being deleted.
+/// Files owned by another package are still listed within its manifest.
+pub fn clear_conflicts(conflicts: &[Conflict], backup: bool) -> std::io::Result<()> {
+    for conflict in conflicts {
+        if backup {
+            let mut name = conflict.path.clone().into_os_string();
+            name.push(".pur-backup");
+
+            fs::rename(&conflict.path, name)?;
+        } else {
+            fs::remove_file(&conflict.path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Computes the lines of the `checksums` file of a package, one `<sha256> <file>` line
+/// for every one of its sources (see [Package::sources]), which is the format sources are verified against.
+///
+/// Sources with a URL are downloaded into the [build_dir] first, any other source is a file
+/// relative to the package directory. Either way, the file is the last component of the source.
+pub fn source_checksums(package: &Package) -> Result<Vec<String>, ParseError> {
+    let (files, paths): (Vec<String>, Vec<PathBuf>) = package
+        .fetch_sources(&package.dir, &sources_dir(&build_dir(), &package.name))?
+        .into_iter()
+        .unzip();
+
+    checksum::sha256_files(&paths)
+        .into_iter()
+        .zip(files)
+        .map(|(sum, file)| Ok(format!("{} {}", sum?, file)))
+        .collect()
+}
+
+// Where the sources of a package with a URL are downloaded into, see [Package::fetch_sources].
+fn sources_dir(build_dir: &Path, name: &str) -> PathBuf {
+    build_dir.join(format!("{}.sources", name))
+}
+
+// The checksum recorded for a file within the content of a `checksums` file, if there's any.
+fn expected_checksum(checksums: &str, file: &str) -> Option<String> {
+    checksums
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .find(|fields| fields.get(1) == Some(&file))
+        .map(|fields| fields[0].to_lowercase())
+}
+
+/// The version of the layout of the install database, read from its `db-version` file.
+///
+/// Databases created before the layout was versioned don't have one.
+pub fn db_version() -> Option<String> {
+    fs::read_to_string(db_root().join("db-version"))
+        .ok()
+        .map(|version| version.trim().to_owned())
+        .filter(|version| !version.is_empty())
+}
+
+/// Every package within the install database together with its install data, sorted by name.
+///
+/// This reads the database directly, so it includes packages that aren't within any repository
+/// anymore. Packages that are built but not installed are only included with `built`.
+pub fn installed_packages(built: bool) -> Vec<(String, InstallData)> {
+    installed_packages_in(&db_root(), built)
+}
+
+fn installed_packages_in(db: &Path, built: bool) -> Vec<(String, InstallData)> {
+    let index = InstalledIndex::scan(db);
+
+    let mut packages = index
+        .built
+        .iter()
+        .filter(|name| built || index.is_installed(name))
+        .flat_map(|name| {
+            InstallData::try_from(db.join(name))
+                .ok()
+                .map(|data| (name.clone(), data))
+        })
+        .collect::<Vec<(String, InstallData)>>();
+
+    packages.sort_by(|(a, _), (b, _)| a.cmp(b));
+    packages
+}
+
+/// The names of the installed packages that depend on the package with the given name, sorted.
+///
+/// The dependencies are read from the install database, so packages that aren't within any
+/// repository anymore are found as well. Packages built before their dependencies were recorded
+/// fall back to the `depends` file of the package with their name within `packages`.
+pub fn dependents(name: &str, packages: &[Package]) -> Vec<String> {
+    dependents_in(&db_root(), name, packages)
+}
+
+fn dependents_in(db: &Path, name: &str, packages: &[Package]) -> Vec<String> {
+    installed_packages_in(db, false)
+        .into_iter()
+        .filter(|(installed, data)| {
+            installed != name
+                && match &data.depends {
+                    Some(depends) => depends.iter().any(|depend| depend == name),
+                    None => packages
+                        .iter()
+                        .find(|package| &package.name == installed)
+                        .is_some_and(|package| {
+                            package.depends.iter().any(|depend| depend.name == name)
+                        }),
+                }
+        })
+        .map(|(installed, _)| installed)
+        .collect()
+}
+
+/// The names of the packages whose removal got interrupted, e.g. by a crash between
+/// removing their links and deleting their directory. These still have a `removing` marker.
+pub fn interrupted_removals() -> Vec<String> {
+    interrupted_removals_in(&db_root())
+}
+
+fn interrupted_removals_in(db: &Path) -> Vec<String> {
+    let mut names = fs::read_dir(db)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().join("removing").exists())
+                .flat_map(|entry| entry.file_name().into_string())
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+
+    names.sort();
+    names
+}
+
+/// Finishes the interrupted removal of a package, see [interrupted_removals].
+///
+/// This doesn't require the package to still be within any repository.
+pub fn finish_removal(name: &str) -> Result<(), ParseError> {
+    ensure_db_writable()?;
+
+    let result = finish_removal_in(
+        &db_root(),
+        &install_root(),
+        &InstallFileStructure::new(name),
+        name,
+    );
+
+    invalidate_index();
+    result
+}
+
+fn finish_removal_in(
+    db: &Path,
+    root: &Path,
+    structure: &InstallFileStructure,
+    name: &str,
+) -> Result<(), ParseError> {
+    let installed_dir = db.join(name);
+
+    remove_links(&installed_dir, root, structure)?;
+    fs::remove_dir_all(installed_dir)?;
+
+    Ok(())
+}
+
+/// Deletes the build logs of every package within the install database.
+/// Returns the amount of deleted logs.
+pub fn purge_build_logs() -> Result<usize, ParseError> {
+    let path = db_root();
+
+    if !path.exists() {
+        return Ok(0);
+    }
+
+    let mut deleted = 0;
+
+    for entry in fs::read_dir(&path)?.flatten() {
+        deleted += script::purge_logs(&entry.path().join("build.log"))?;
+    }
+
+    Ok(deleted)
+}
+
+/// Deletes the build directories of every package within the [build_dir].
+/// Returns the amount of deleted directories.
+pub fn purge_build_dirs() -> Result<usize, ParseError> {
+    let path = build_dir();
+
+    if !path.exists() {
+        return Ok(0);
+    }
+
+    let mut deleted = 0;
+
+    for entry in fs::read_dir(&path)?.flatten() {
+        fs::remove_dir_all(entry.path())?;
+        deleted += 1;
+    }
+
+    Ok(deleted)
+}
+
+/// Creates the install database if it doesn't exist yet, and checks whether we're able to write to it.
+///
+/// This should be called before modifying the database, so a read-only mount results in a clear
+/// error up front, instead of failing halfway through an operation.
+pub fn ensure_db_writable() -> Result<(), ParseError> {
+    let path = db_root();
+
+    let read_only = |e: std::io::Error| match e.kind() {
+        ErrorKind::ReadOnlyFilesystem => {
+            ParseError::ReadOnlyDatabase(format!("{} is on a read-only mount", path.display()))
+        }
+        ErrorKind::PermissionDenied => ParseError::ReadOnlyDatabase(format!(
+            "no permission to write to {}, are you root?",
+            path.display()
+        )),
+        _ => ParseError::from(e),
+    };
+
+    fs::create_dir_all(&path).map_err(read_only)?;
+
+    // The permissions of the directory don't tell us about read-only mounts,
+    // so the only reliable way to find out is actually writing something.
+    let probe = path.join(".pur-write-test");
+    File::create(&probe).map_err(read_only)?;
+    let _ = fs::remove_file(probe);
+
+    Ok(())
+}
+
+// Reads the non-empty lines of an optional file, together with their line numbers.
+fn read_lines(path: &Path) -> Vec<(usize, String)> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| (i + 1, line.trim().to_owned()))
+        .collect()
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .map(|meta| meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(target_os = "windows")]
+fn is_executable(_: &Path) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(dir: &Path, name: &str, files: &[(&str, &str)]) -> Package {
+        let dir = dir.join(name);
+        fs::create_dir_all(&dir).unwrap();
+
+        for (file, content) in files {
+            fs::write(dir.join(file), content).unwrap();
+        }
+
+        Package::try_from(dir).unwrap()
+    }
+
+    // Writes the install script of the package within `dir`, which has to be executable to run directly.
+    fn executable_script(dir: &Path, body: &str) {
+        let install = dir.join("install");
+        fs::write(&install, body).unwrap();
+
+        let mut permissions = fs::metadata(&install).unwrap().permissions();
+        std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o755);
+        fs::set_permissions(&install, permissions).unwrap();
+    }
+
+    // The default configuration with packages built within `build_dir`, leaving out the config
+    // file and environment of the machine the tests run on.
+    fn config(build_dir: &Path) -> Config {
+        let build_dir = build_dir.display().to_string();
+
+        Config::layer(&HashMap::new(), &|var| {
+            (var == "PUR_BUILD_DIR").then(|| build_dir.clone())
+        })
+    }
+
+    #[test]
+    fn validate_finds_problems() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let dep = package(dir.path(), "dep", &[("version", "1.0"), ("depends", "")]);
+        let broken = package(
+            dir.path(),
+            "broken",
+            &[
+                ("version", ""),
+                ("depends", "dep\nmissing\ndep\n"),
+                ("install", "#!/bin/sh\n"),
+                ("checksums", "abc file.tar.gz\n"),
+                (
+                    "sources",
+                    "https://example.org/a.tar.gz\n\nhttps://a https://b\n",
+                ),
+            ],
+        );
+
+        assert_eq!(
+            broken.validate(&[dep.clone(), broken.clone()]),
+            vec![
+                ValidationWarning::InstallScriptNotExecutable,
+                ValidationWarning::EmptyVersion,
+                ValidationWarning::MissingDepend("missing".into()),
+                ValidationWarning::DuplicateDepend("dep".into()),
+                ValidationWarning::MalformedChecksum {
+                    line: 1,
+                    content: "abc file.tar.gz".into()
+                },
+                ValidationWarning::MalformedSource {
+                    line: 3,
+                    content: "https://a https://b".into()
+                },
+            ]
+        );
+
+        assert_eq!(dep.validate(&[]), vec![ValidationWarning::NoInstallScript]);
+    }
+
+    #[test]
+    fn finds_stale_links() {
+        let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<PathBuf>>();
+
+        assert_eq!(
+            stale_links(
+                &paths(&[
+                    "/usr/bin/foo",
+                    "/usr/bin/foo-legacy",
+                    "/usr/lib/libfoo.so.1"
+                ]),
+                &paths(&["/usr/bin/foo", "/usr/lib/libfoo.so.2"]),
+            ),
+            paths(&["/usr/bin/foo-legacy", "/usr/lib/libfoo.so.1"])
+        );
+        assert!(stale_links(&[], &paths(&["/usr/bin/foo"])).is_empty());
+    }
+
+    #[test]
+    fn builds_pinned_definition() {
+        let repo = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(repo.path())
+                .args(["-c", "user.name=pur", "-c", "user.email=pur@localhost"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+
+            assert!(status.success(), "git {:?} failed", args);
+        };
+
+        git(&["init", "-q"]);
+        package(
+            repo.path(),
+            "foo",
+            &[("version", "1.0"), ("depends", "bar"), ("install", "v1")],
+        );
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "foo 1.0"]);
+        git(&["tag", "reviewed"]);
+
+        let foo = package(
+            repo.path(),
+            "foo",
+            &[
+                ("version", "2.0"),
+                ("depends", "bar\nbaz"),
+                ("install", "v2"),
+                ("ref", "reviewed # don't build 2.0 yet\n"),
+            ],
+        );
+        git(&["commit", "-q", "-am", "foo 2.0"]);
+
+        assert_eq!(foo.pin(), Some("reviewed"));
+        assert_eq!(foo.version.as_str(), "1.0");
+        assert_eq!(foo.depends, ["bar".parse().unwrap()]);
+        assert_eq!(foo.held_back().map(|v| v.to_string()), Some("2.0".into()));
+
+        let definition = repo.path().join("definition");
+        git::export(foo.dir(), "reviewed", &definition).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(definition.join("install")).unwrap(),
+            "v1"
+        );
+        assert!(!definition.join("ref").exists());
+    }
+
+    #[test]
+    fn orders_build_phases() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = |package: &Package| {
+            package
+                .phases(&config(dir.path()), package.dir())
+                .unwrap()
+                .iter()
+                .map(|phase| phase.file_name().unwrap().to_string_lossy().into_owned())
+                .collect::<Vec<String>>()
+        };
+
+        let combined = package(
+            dir.path(),
+            "combined",
+            &[("version", "1.0"), ("depends", ""), ("install", "")],
+        );
+        let split = package(
+            dir.path(),
+            "split",
+            &[
+                ("version", "1.0"),
+                ("depends", ""),
+                ("build", ""),
+                ("package", ""),
+                ("install", ""),
+            ],
+        );
+        let missing = package(
+            dir.path(),
+            "missing",
+            &[("version", "1.0"), ("depends", "")],
+        );
+
+        assert_eq!(names(&combined), ["install"]);
+        assert_eq!(names(&split), ["build", "package"]);
+        assert!(matches!(
+            missing.phases(&config(dir.path()), missing.dir()),
+            Err(ParseError::NoInstallScript { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_non_unicode_names() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let name = OsStr::from_bytes(b"caf\xe9");
+
+        let package = dir.path().join(name);
+        fs::create_dir_all(&package).unwrap();
+        fs::write(package.join("version"), "1.0").unwrap();
+        fs::write(package.join("depends"), "").unwrap();
+
+        assert!(matches!(
+            Package::try_from(package.clone()),
+            Err(ParseError::InvalidName(path)) if path == package
+        ));
+
+        // it isn't mistaken for a package within the install database either.
+        fs::write(package.join("installed"), "").unwrap();
+        let index = InstalledIndex::scan(dir.path());
+
+        assert!(index.installed.is_empty() && index.built.is_empty());
+    }
+
+    #[test]
+    fn meta_packages_skip_the_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = |install: Option<&'static str>, meta: bool| {
+            let mut files = vec![("version", "1.0"), ("depends", "foo\nbar")];
+            files.extend(install.map(|content| ("install", content)));
+            files.extend(meta.then_some(("meta", "")));
+            files
+        };
+
+        let absent = package(dir.path(), "absent", &files(None, true));
+        let empty = package(dir.path(), "empty", &files(Some(""), true));
+        let scripted = package(dir.path(), "scripted", &files(Some("#!/bin/sh\n"), true));
+        let regular = package(dir.path(), "regular", &files(None, false));
+        let config = config(dir.path());
+
+        assert!(absent.is_placeholder(&config, absent.dir()));
+        assert!(empty.is_placeholder(&config, empty.dir()));
+        assert!(!scripted.is_placeholder(&config, scripted.dir()));
+        assert!(!regular.is_placeholder(&config, regular.dir()));
+
+        // a meta-package doesn't need an install script.
+        assert!(!absent
+            .validate(&[])
+            .contains(&ValidationWarning::NoInstallScript));
+        assert!(regular
+            .validate(&[])
+            .contains(&ValidationWarning::NoInstallScript));
+    }
+
+    #[test]
+    fn records_source_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let foo = package(
+            dir.path(),
+            "foo",
+            &[
+                ("version", "1.0"),
+                ("depends", ""),
+                ("sources", "patches/fix.patch\n\n${name}-${version}.conf\n"),
+            ],
+        );
+
+        fs::create_dir_all(foo.dir().join("patches")).unwrap();
+        fs::write(foo.dir().join("patches/fix.patch"), "").unwrap();
+        fs::write(foo.dir().join("foo-1.0.conf"), "abc").unwrap();
+
+        assert_eq!(
+            source_checksums(&foo).unwrap(),
+            [
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855 fix.patch",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad foo-1.0.conf",
+            ]
+        );
+    }
+
+    #[test]
+    fn verifies_sources_before_building() {
+        let dir = tempfile::tempdir().unwrap();
+        let build = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let config = config(build.path());
+        let verified = package(
+            dir.path(),
+            "pur-test-checksums",
+            &[
+                ("version", "1.0"),
+                ("depends", ""),
+                ("sources", "fix.patch\n"),
+                ("fix.patch", "abc"),
+            ],
+        );
+
+        executable_script(
+            verified.dir(),
+            "#!/bin/sh\nmkdir -p \"$1/usr/bin\"\ntouch \"$1/usr/bin/verified\"\n",
+        );
+
+        let sum = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let checksums = verified.dir().join("checksums");
+
+        // the install script never runs for a source that doesn't match.
+        fs::write(&checksums, format!("{} fix.patch\n", "0".repeat(64))).unwrap();
+        assert!(matches!(
+            verified.build_to_with(&config, output.path()),
+            Err(ParseError::ChecksumMismatch { file, got, .. }) if file == "fix.patch" && got == sum
+        ));
+        assert!(!output.path().join("usr/bin/verified").exists());
+
+        fs::write(&checksums, format!("{} fix.patch\n", sum)).unwrap();
+        verified.build_to_with(&config, output.path()).unwrap();
+        assert!(output.path().join("usr/bin/verified").exists());
+
+        // packages without checksums aren't verified at all.
+        fs::remove_file(&checksums).unwrap();
+        fs::write(verified.dir().join("fix.patch"), "changed").unwrap();
+        assert!(verified.build_to_with(&config, output.path()).is_ok());
+    }
+
+    #[test]
+    fn expands_source_placeholders() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = |content: &str| {
+            package(
+                dir.path(),
+                "pfetch",
+                &[("version", "0.6.0"), ("depends", ""), ("sources", content)],
+            )
+            .sources()
+        };
+
+        assert_eq!(
+            sources("https://example.org/${name}/${name}-${version}.tar.gz\n").unwrap(),
+            ["https://example.org/pfetch/pfetch-0.6.0.tar.gz"]
+        );
+        assert!(matches!(
+            sources("patch\nhttps://example.org/${release}.tar.gz\n"),
+            Err(ParseError::InvalidSource { line: 2, reason }) if reason.contains("${release}")
+        ));
+        assert!(matches!(
+            sources("https://example.org/${version.tar.gz\n"),
+            Err(ParseError::InvalidSource { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn replaces_build_info_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("build-info"),
+            "profile = release\ninstall_time = 0.10s\n",
+        )
+        .unwrap();
+
+        set_build_info(dir.path(), "install_time", "0.25s").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("build-info")).unwrap(),
+            "profile = release\ninstall_time = 0.25s\n"
+        );
+    }
+
+    #[test]
+    fn failing_install_scripts_fail_the_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let build = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let config = config(build.path());
+        let failing = package(
+            dir.path(),
+            "pur-test-exit-status",
+            &[("version", "1.0"), ("depends", "")],
+        );
+
+        executable_script(
+            failing.dir(),
+            "#!/bin/sh\nmkdir -p \"$1/usr/bin\"\necho 'cc: not found' >&2\nexit 1\n",
+        );
+
+        assert!(matches!(
+            failing.build_to_with(&config, output.path()),
+            Err(ParseError::FailedInstallScript(tail)) if tail == ["cc: not found"]
+        ));
+        assert!(fs::read_dir(output.path()).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn passes_package_to_install_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let build = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let config = config(build.path());
+        let described = package(
+            dir.path(),
+            "pur-test-script-env",
+            &[("version", "2.1"), ("depends", "")],
+        );
+
+        executable_script(
+            described.dir(),
+            "#!/bin/sh\nmkdir -p \"$1/usr/bin\"\nenv > \"$PUR_FILES_DIR/usr/bin/env\"\n",
+        );
+
+        described.build_to_with(&config, output.path()).unwrap();
+
+        let env = fs::read_to_string(output.path().join("usr/bin/env")).unwrap();
+        let files_dir = build.path().join("pur-test-script-env");
+
+        assert!(env.contains("PUR_PKG_NAME=pur-test-script-env\n"));
+        assert!(env.contains("PUR_PKG_VERSION=2.1\n"));
+        assert!(env.contains(&format!("PUR_FILES_DIR={}\n", files_dir.display())));
+        assert!(env.contains(&format!("PUR_PKG_DIR={}\n", described.dir().display())));
+    }
+
+    #[test]
+    fn runs_scripts_within_their_build_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let build = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let config = config(build.path());
+        let located = package(
+            dir.path(),
+            "pur-test-script-dir",
+            &[("version", "1.0"), ("depends", "")],
+        );
+
+        executable_script(
+            located.dir(),
+            "#!/bin/sh\nmkdir -p \"$1/usr/bin\"\npwd -P > \"$1/usr/bin/pwd\"\n",
+        );
+
+        located.build_to_with(&config, output.path()).unwrap();
+
+        let pwd = fs::read_to_string(output.path().join("usr/bin/pwd")).unwrap();
+        let files_dir = build.path().join("pur-test-script-dir");
+
+        assert_eq!(Path::new(pwd.trim()), fs::canonicalize(files_dir).unwrap());
+    }
+
+    #[test]
+    fn indexes_install_database() {
+        let db = tempfile::tempdir().unwrap();
+
+        for (name, markers) in [
+            ("installed", &["version", "installed"][..]),
+            ("built", &["version"][..]),
+            ("removing", &["version", "installed", "removing"][..]),
+            ("failed", &["build.log"][..]),
+        ] {
+            fs::create_dir_all(db.path().join(name)).unwrap();
+
+            for marker in markers {
+                File::create(db.path().join(name).join(marker)).unwrap();
+            }
+        }
+
+        fs::write(db.path().join(".pur-write-test"), "").unwrap();
+
+        let index = InstalledIndex::scan(db.path());
+
+        assert!(index.is_installed("installed") && index.is_built("installed"));
+        assert!(!index.is_installed("built") && index.is_built("built"));
+        assert!(!index.is_installed("removing") && !index.is_built("removing"));
+        assert!(!index.is_built("failed"));
+        assert!(!index.is_built(".pur-write-test"));
+
+        // a fresh system doesn't have a database yet, querying it mustn't create one.
+        assert!(!InstalledIndex::scan(&db.path().join("missing")).is_built("built"));
+        assert!(!db.path().join("missing").exists());
+    }
+
+    #[test]
+    fn lists_install_database() {
+        let db = tempfile::tempdir().unwrap();
+
+        for (name, version, markers) in [
+            ("zlib", "1.3", &["installed"][..]),
+            ("curl", "8.0", &["installed"][..]),
+            ("built", "1.0", &[][..]),
+            ("removing", "1.0", &["installed", "removing"][..]),
+        ] {
+            let dir = db.path().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("version"), version).unwrap();
+
+            for marker in markers {
+                File::create(dir.join(marker)).unwrap();
+            }
+        }
+
+        // an entry without a version can't be listed.
+        fs::create_dir_all(db.path().join("broken")).unwrap();
+        File::create(db.path().join("broken/installed")).unwrap();
+
+        let list = |built: bool| {
+            installed_packages_in(db.path(), built)
+                .into_iter()
+                .map(|(name, data)| format!("{} {}", name, data.version))
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(list(false), ["curl 8.0", "zlib 1.3"]);
+        assert_eq!(list(true), ["built 1.0", "curl 8.0", "zlib 1.3"]);
+    }
+
+    #[test]
+    fn finds_installed_dependents() {
+        let db = tempfile::tempdir().unwrap();
+        let repo = tempfile::tempdir().unwrap();
+
+        for (name, depends, markers) in [
+            ("curl", Some("zlib\nopenssl\n"), &["installed"][..]),
+            ("git", Some("curl\n"), &["installed"][..]),
+            ("built", Some("zlib\n"), &[][..]),
+            ("legacy", None, &["installed"][..]),
+        ] {
+            let dir = db.path().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("version"), "1.0").unwrap();
+
+            if let Some(depends) = depends {
+                fs::write(dir.join("depends"), depends).unwrap();
+            }
+
+            for marker in markers {
+                File::create(dir.join(marker)).unwrap();
+            }
+        }
+
+        // the dependencies of packages built before they were recorded come from the repository.
+        let packages = [package(
+            repo.path(),
+            "legacy",
+            &[("version", "1.0"), ("depends", "zlib>=1.2")],
+        )];
+
+        assert_eq!(
+            dependents_in(db.path(), "zlib", &packages),
+            ["curl", "legacy"]
+        );
+        assert_eq!(dependents_in(db.path(), "curl", &packages), ["git"]);
+        assert!(dependents_in(db.path(), "git", &packages).is_empty());
+    }
+
+    #[test]
+    fn depends_ignore_comments() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let package = package(
+            dir.path(),
+            "curl",
+            &[
+                ("version", "8.0"),
+                (
+                    "depends",
+                    "# libraries\nopenssl # for TLS\n  zlib  \n\n#nghttp2\nca-certificates#\n",
+                ),
+            ],
+        );
+
+        let depends = package
+            .depends
+            .iter()
+            .map(|depend| depend.name.as_str())
+            .collect::<Vec<&str>>();
+
+        assert_eq!(depends, ["openssl", "zlib", "ca-certificates"]);
+    }
+
+    #[test]
+    fn conditional_depends_follow_features() {
+        let content = "openssl\nx11: libxcb # for the gui\n debug : gdb\nzlib\n";
+        let features = |enabled: &[&str]| {
+            enabled
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect::<Vec<String>>()
+        };
+
+        let names = |depends: &[Depend]| {
+            depends
+                .iter()
+                .map(|depend| depend.name.clone())
+                .collect::<Vec<String>>()
+        };
+
+        let (depends, conditional) = parse_depends(content, &features(&[])).unwrap();
+        assert_eq!(names(&depends), ["openssl", "zlib"]);
+        assert_eq!(
+            conditional,
+            [
+                ConditionalDepend {
+                    feature: "x11".to_owned(),
+                    depend: "libxcb".parse().unwrap()
+                },
+                ConditionalDepend {
+                    feature: "debug".to_owned(),
+                    depend: "gdb".parse().unwrap()
+                },
+            ]
+        );
+
+        let (depends, _) = parse_depends(content, &features(&["x11"])).unwrap();
+        assert_eq!(names(&depends), ["openssl", "libxcb", "zlib"]);
+
+        let (depends, _) = parse_depends(content, &features(&["debug", "x11", "wayland"])).unwrap();
+        assert_eq!(names(&depends), ["openssl", "libxcb", "gdb", "zlib"]);
+    }
+
+    #[test]
+    fn parses_depend_constraints() {
+        let content = "openssl>=3.0\nzlib\nlibfoo = 1:2.0 # epoch\nx11: libxcb < 2\n";
+
+        let (depends, conditional) = parse_depends(content, &[]).unwrap();
+        let depends = depends
+            .iter()
+            .map(|depend| depend.to_string())
+            .collect::<Vec<String>>();
+
+        assert_eq!(depends, ["openssl>=3.0", "zlib", "libfoo=1:2.0"]);
+        assert_eq!(conditional[0].depend.to_string(), "libxcb<2");
+
+        let openssl = "openssl>=3.0".parse::<Depend>().unwrap();
+        assert!(openssl.is_satisfied_by(&"3.1".parse().unwrap()));
+        assert!(!openssl.is_satisfied_by(&"1.1.1".parse().unwrap()));
+        assert!("zlib"
+            .parse::<Depend>()
+            .unwrap()
+            .is_satisfied_by(&"0.1".parse().unwrap()));
+
+        assert!(matches!(
+            parse_depends("openssl\nzlib >=\n", &[]),
+            Err(ParseError::InvalidDepend { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse_depends(">=1.0\n", &[]),
+            Err(ParseError::InvalidDepend { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn build_env_overrides_clean_setting() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let plain = package(dir.path(), "plain", &[("version", "1.0"), ("depends", "")]);
+        let dirty = package(
+            dir.path(),
+            "dirty",
+            &[
+                ("version", "1.0"),
+                ("depends", ""),
+                ("env", "PUR_CLEAN_ENV=no\nCFLAGS=-O2\n"),
+            ],
+        );
+
+        assert_eq!(build_env(plain.dir(), true), (true, HashMap::new()));
+
+        let (clean, env) = build_env(dirty.dir(), true);
+
+        assert!(!clean);
+        assert_eq!(env.get("CFLAGS").map(String::as_str), Some("-O2"));
+        assert!(!env.contains_key("PUR_CLEAN_ENV"));
+    }
+
+    #[test]
+    fn reports_every_conflict() {
+        let db = tempfile::tempdir().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let link = |path: &str| root.path().join(path);
+
+        let structure = InstallFileStructure::with_prefix("new", Path::new("/usr"))
+            .in_dir(&db.path().join("new/files"));
+
+        for file in ["usr/bin/a", "usr/bin/b", "usr/bin/c", "usr/lib/d"] {
+            let path = db.path().join("new/files").join(file);
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        // a and b belong to old, c is just lying around, d is ours already.
+        fs::create_dir_all(link("usr/bin")).unwrap();
+        fs::create_dir_all(link("usr/lib")).unwrap();
+        fs::write(link("usr/bin/c"), "").unwrap();
+        std::os::unix::fs::symlink(db.path().join("new/files/usr/lib/d"), link("usr/lib/d"))
+            .unwrap();
+
+        fs::create_dir_all(db.path().join("old")).unwrap();
+        Manifest::from_paths(root.path(), &[link("usr/bin/a"), link("usr/bin/b")])
+            .write(&db.path().join("old/manifest"))
+            .unwrap();
+
+        let mut conflicts = conflicts_in(db.path(), root.path(), "new", &structure).unwrap();
+        conflicts.sort_by(|x, y| x.path.cmp(&y.path));
+
+        let old = Some("old".to_owned());
+
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict {
+                    path: link("usr/bin/a"),
+                    owner: old.clone()
+                },
+                Conflict {
+                    path: link("usr/bin/b"),
+                    owner: old
+                },
+                Conflict {
+                    path: link("usr/bin/c"),
+                    owner: None
+                },
+            ]
+        );
+
+        clear_conflicts(&conflicts[2..], true).unwrap();
+
+        assert!(!link("usr/bin/c").exists());
+        assert!(link("usr/bin/c.pur-backup").exists());
+    }
+
+    #[test]
+    fn finds_owners_through_links() {
+        let db = tempfile::tempdir().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let link = |path: &str| root.path().join(path);
+
+        let structure = InstallFileStructure::with_prefix("new", Path::new("/usr"))
+            .in_dir(&db.path().join("new/files"));
+
+        for file in ["new/files/usr/bin/foo", "old/files/usr/bin/foo"] {
+            let path = db.path().join(file);
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        // old was installed before manifests were recorded.
+        fs::create_dir_all(link("usr/bin")).unwrap();
+        std::os::unix::fs::symlink(db.path().join("old/files/usr/bin/foo"), link("usr/bin/foo"))
+            .unwrap();
+        fs::write(link("usr/bin/plain"), "").unwrap();
+
+        assert_eq!(
+            owner_in(db.path(), &link("usr/bin/foo")),
+            Some("old".into())
+        );
+        assert_eq!(owner_in(db.path(), &link("usr/bin/plain")), None);
+        assert_eq!(owner_in(db.path(), &link("usr/bin/missing")), None);
+
+        assert_eq!(
+            conflicts_in(db.path(), root.path(), "new", &structure).unwrap(),
+            vec![Conflict {
+                path: link("usr/bin/foo"),
+                owner: Some("old".into())
+            }]
+        );
+    }
+
+    #[test]
+    fn reconstructs_manifest_from_links() {
+        let db = tempfile::tempdir().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let files = db.path().join("foo/files");
+        let file = |path: &str| files.join(path);
+        let link = |path: &str| root.path().join(path);
+
+        for path in [
+            "usr/bin/foo",
+            "usr/bin/bar",
+            "usr/lib/libfoo.so",
+            "usr/bin/unlinked",
+        ] {
+            fs::create_dir_all(file(path).parent().unwrap()).unwrap();
+            fs::write(file(path), "").unwrap();
+        }
+
+        fs::create_dir_all(link("usr/bin")).unwrap();
+        fs::create_dir_all(link("usr/lib")).unwrap();
+        std::os::unix::fs::symlink(file("usr/bin/foo"), link("usr/bin/foo")).unwrap();
+        std::os::unix::fs::symlink(file("usr/lib/libfoo.so"), link("usr/lib/libfoo.so")).unwrap();
+        // bar belongs to another package, which was installed over it.
+        std::os::unix::fs::symlink("/elsewhere/bar", link("usr/bin/bar")).unwrap();
+
+        let structure = InstallFileStructure::new("foo").in_dir(&files);
+        let manifest = manifest_from_links(root.path(), &structure).unwrap();
+
+        let mut entries = manifest.entries().to_vec();
+        entries.sort();
+
+        assert_eq!(
+            entries,
+            [
+                PathBuf::from("usr/bin/foo"),
+                PathBuf::from("usr/lib/libfoo.so")
+            ]
+        );
+    }
+
+    #[test]
+    fn finishes_interrupted_removal() {
+        let db = tempfile::tempdir().unwrap();
+        let root = tempfile::tempdir().unwrap();
+
+        // foo was installed, and its removal crashed right after writing the marker.
+        let installed_dir = db.path().join("foo");
+        let file = installed_dir.join("files/usr/bin/foo");
+        let link = root.path().join("usr/bin/foo");
+
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::create_dir_all(link.parent().unwrap()).unwrap();
+        fs::write(&file, "").unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        Manifest::from_paths(root.path(), std::slice::from_ref(&link))
+            .write(&installed_dir.join("manifest"))
+            .unwrap();
+        File::create(installed_dir.join("installed")).unwrap();
+        File::create(installed_dir.join("removing")).unwrap();
+
+        // bar is installed just fine.
+        fs::create_dir_all(db.path().join("bar")).unwrap();
+        File::create(db.path().join("bar/installed")).unwrap();
+
+        assert_eq!(interrupted_removals_in(db.path()), vec!["foo".to_owned()]);
+
+        finish_removal_in(
+            db.path(),
+            root.path(),
+            &InstallFileStructure::new("foo"),
+            "foo",
+        )
+        .unwrap();
+
+        assert!(!installed_dir.exists());
+        assert!(link.symlink_metadata().is_err());
+        assert!(interrupted_removals_in(db.path()).is_empty());
+    }
+}
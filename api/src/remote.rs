@@ -0,0 +1,200 @@
+use crate::{config::cache_dir, error::UpdateError, fetch};
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
+};
+
+// The file listing the packages of a remote repository, relative to its URL.
+const INDEX: &str = "index";
+
+// Where the index is kept within the mirror, hidden so it isn't taken for a package.
+const MIRRORED_INDEX: &str = ".remote-index";
+
+/// A package of a remote repository, as listed within its index: its name,
+/// and the files of its definition together with whether they're executable.
+type Listing = (String, Vec<(PathBuf, bool)>);
+
+/// Whether an entry of the `repos` setting is the URL of a remote repository, rather than a directory.
+pub fn is_remote(entry: &str) -> bool {
+    entry.starts_with("http://") || entry.starts_with("https://")
+}
+
+/// The directory a remote repository is mirrored into within the [cache_dir], named after its URL.
+pub fn mirror_dir(url: &str) -> PathBuf {
+    let name = url.replace('%', "%25").replace('/', "%2F");
+
+    cache_dir().join("remote").join(name)
+}
+
+/// Downloads the remote repository at `url` into `target`, which is a repository
+/// directory like any other afterwards, so its packages are read and built from there.
+///
+/// A remote repository is served as a mirror of a repository directory. Its `index` lists a
+/// package per line, as its name followed by the files of its definition, relative to the
+/// package directory. Files marked with a trailing `*` are executable, e.g. the install script:
+///
+/// ```text
+/// # comments and empty lines are ignored
+/// curl version depends sources checksums install*
+/// zlib version depends patches/fix.patch install*
+/// ```
+///
+/// Every file is downloaded from `<url>/<package>/<file>`. The mirror is only swapped in once
+/// everything is downloaded, so a failed update leaves the previous mirror in place.
+///
+/// Plain `http://` URLs are refused, as the install scripts within the mirror are run as root
+/// and nothing else would tell whether they were tampered with on the way.
+pub fn mirror(url: &str, target: &Path) -> Result<(), UpdateError> {
+    if url.starts_with("http://") {
+        return Err(UpdateError::FetchError(format!(
+            "{} isn't served over HTTPS, refusing to mirror it",
+            url
+        )));
+    }
+
+    let url = url.trim_end_matches('/');
+    let failed = |e: std::io::Error| UpdateError::FetchError(e.to_string());
+
+    // the name of the mirror is a URL, which has dots that aren't an extension.
+    let mut partial = target.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let _ = fs::remove_dir_all(&partial);
+    fs::create_dir_all(&partial).map_err(failed)?;
+
+    let index = partial.join(MIRRORED_INDEX);
+    let index_url = format!("{}/{}", url, INDEX);
+    fetch::download(&index_url, &index, fetch::reporter(&index_url)).map_err(failed)?;
+
+    let listings = parse_index(&fs::read_to_string(&index).map_err(failed)?)
+        .map_err(UpdateError::FetchError)?;
+
+    for (package, files) in listings {
+        for (file, executable) in files {
+            let path = partial.join(&package).join(&file);
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(failed)?;
+            }
+
+            let file_url = format!("{}/{}/{}", url, package, file.to_string_lossy());
+            fetch::download(&file_url, &path, fetch::reporter(&file_url)).map_err(failed)?;
+
+            if executable {
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).map_err(failed)?;
+            }
+        }
+    }
+
+    let _ = fs::remove_dir_all(target);
+    fs::rename(&partial, target).map_err(failed)
+}
+
+// Parses the index of a remote repository, see [mirror]. Nothing within it may point outside
+// of its package, as the index is as trustworthy as whoever serves it.
+fn parse_index(content: &str) -> Result<Vec<Listing>, String> {
+    let mut listings = Vec::<Listing>::new();
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let package = fields.next().unwrap_or_default();
+
+        if package.starts_with('.') || package.contains('/') {
+            return Err(format!("invalid package name {} within the index", package));
+        }
+
+        let files = fields
+            .map(|file| {
+                let (file, executable) = match file.strip_suffix('*') {
+                    Some(file) => (file, true),
+                    None => (file, false),
+                };
+                let path = PathBuf::from(file);
+
+                match path.components().all(|c| matches!(c, Component::Normal(_))) {
+                    true => Ok((path, executable)),
+                    false => Err(format!("{} of {} points outside of it", file, package)),
+                }
+            })
+            .collect::<Result<Vec<(PathBuf, bool)>, String>>()?;
+
+        listings.push((package.to_owned(), files));
+    }
+
+    Ok(listings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrors_remote_repositories() {
+        let served = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let url = format!("file://{}/", served.path().display());
+        let target = cache.path().join("mirror");
+        let serve = |file: &str, content: &str| {
+            let path = served.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+
+        serve(
+            INDEX,
+            "# packages\ncurl version depends patches/fix.patch install*\n\n",
+        );
+        serve("curl/version", "8.0");
+        serve("curl/depends", "zlib\n");
+        serve("curl/patches/fix.patch", "abc");
+        serve("curl/install", "#!/bin/sh\n");
+
+        mirror(&url, &target).unwrap();
+
+        let curl = target.join("curl");
+        assert_eq!(fs::read_to_string(curl.join("version")).unwrap(), "8.0");
+        assert_eq!(
+            fs::read_to_string(curl.join("patches/fix.patch")).unwrap(),
+            "abc"
+        );
+        assert_eq!(
+            fs::metadata(curl.join("install"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o755
+        );
+        assert_eq!(
+            fs::metadata(curl.join("depends"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o111,
+            0
+        );
+
+        // a failed update, here as the index points outside of the mirror, keeps the previous mirror.
+        serve(INDEX, "curl version ../../etc/passwd\n");
+        assert!(matches!(
+            mirror(&url, &target),
+            Err(UpdateError::FetchError(_))
+        ));
+        assert_eq!(fs::read_to_string(curl.join("version")).unwrap(), "8.0");
+
+        serve(INDEX, "curl version missing\n");
+        assert!(mirror(&url, &target).is_err());
+        assert!(curl.join("install").exists());
+
+        assert!(matches!(
+            mirror("http://repo.example.com/pur", &target),
+            Err(UpdateError::FetchError(_))
+        ));
+        assert!(curl.join("install").exists());
+    }
+}
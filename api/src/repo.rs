@@ -1,44 +1,119 @@
-use crate::error::{ParseError, UpdateError};
+use crate::config::{cache_dir, script_name, Config};
+use crate::error::{ExecuteError, ParseError, UpdateError};
+use crate::git;
+use crate::index;
 use crate::package::Package;
-use std::env::set_current_dir;
-use std::num::ParseIntError;
+use crate::remote;
+use crate::version::{parse_version, Version};
 use std::process::Command;
-use std::{convert::TryFrom, fs, path::PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{
+    convert::TryFrom,
+    fs,
+    path::{Path, PathBuf},
+};
 
+// The file `pur update` touches within a repository, its modification time is when it was last updated.
+const LAST_UPDATE: &str = ".last-update";
+
+/// A directory within a repository that couldn't be parsed as a package, together with why.
+pub type InvalidPackage = (PathBuf, ParseError);
+
+/// Fetches all repositories from the `repos` setting, a colon separated list of directories,
+/// and URLs of remote repositories (e.g. `https://repo.example.com/pur`), see [remote::mirror].
+///
+/// This is usually set through the `PUR_PATH` environment variable.
 pub fn get_repositories() -> Vec<Repo> {
-    let repo_var = match std::env::var("PUR_PATH") {
-        Ok(val) => val,
-        Err(_) => {
-            let repos = vec![
-                "/usr/repo/pur",
-                "/usr/repo/pur-community",
-                "/usr/repo/unofficial",
-            ];
+    split_repositories(Config::load().value("repos"))
+        .into_iter()
+        .map(|entry| match remote::is_remote(&entry) {
+            true => Repo::remote(&entry),
+            false => Repo::from(PathBuf::from(entry)),
+        })
+        .collect::<Vec<Repo>>()
+}
+
+// Splits the `repos` setting at its colons, except for those within URLs.
+fn split_repositories(value: &str) -> Vec<String> {
+    let mut entries = Vec::<String>::new();
+
+    for part in value.split(':') {
+        let continues = match entries.last() {
+            // the colon after the scheme of a URL isn't a separator,
+            Some(last) if last == "http" || last == "https" => part.starts_with("//"),
+            // and neither is the one before its port.
+            Some(last) if remote::is_remote(last) => {
+                let host = last.split_once("://").map(|(_, rest)| rest);
 
-            repos.join(":")
+                !host.unwrap_or_default().contains('/')
+                    && part.starts_with(|c: char| c.is_ascii_digit())
+            }
+            _ => false,
+        };
+
+        match entries.last_mut() {
+            Some(last) if continues => {
+                last.push(':');
+                last.push_str(part);
+            }
+            _ => entries.push(part.to_owned()),
         }
-    };
+    }
 
-    repo_var
-        .split(":")
-        .map(PathBuf::from)
-        .map(Repo::from)
-        .collect::<Vec<Repo>>()
+    entries
+}
+
+/// Moves the named repositories to the front, in the given order, so their packages take
+/// precedence over the packages of the other repositories. The other repositories keep their order.
+///
+/// A repository can be named by its full path, or by the name of its directory (e.g `pur-community`).
+pub fn with_precedence(repos: Vec<Repo>, precedence: &[&str]) -> Result<Vec<Repo>, ExecuteError> {
+    let mut rest = repos;
+    let mut ordered = Vec::<Repo>::new();
+
+    for name in precedence {
+        let index = rest
+            .iter()
+            .position(|repo| {
+                repo.name() == *name
+                    || repo.dir == PathBuf::from(name)
+                    || repo.url.as_deref() == Some(*name)
+            })
+            .ok_or_else(|| ExecuteError::UnknownRepository(name.to_string()))?;
+
+        ordered.push(rest.remove(index));
+    }
+
+    ordered.append(&mut rest);
+
+    Ok(ordered)
 }
 
 #[derive(Debug, Clone)]
 pub struct InstallData {
-    pub version: String,
+    pub version: Version,
+    /// When the package was installed, read from its `installed` marker.
+    /// This is `None` for packages that are only built, and for those installed
+    /// before the time was recorded, which have an empty marker.
+    pub installed_at: Option<SystemTime>,
+    /// The names of the dependencies the package was built with, read from its `depends` record.
+    /// This is `None` for packages built before their dependencies were recorded.
+    pub depends: Option<Vec<String>>,
 }
 
 #[derive(Debug)]
 pub struct Repo {
     pub dir: PathBuf,
+    /// The URL of a remote repository, of which `dir` is the mirror, see [remote::mirror].
+    pub url: Option<String>,
 }
 
 impl From<PathBuf> for Repo {
     fn from(path: PathBuf) -> Self {
-        Self { dir: path }
+        Self {
+            dir: path,
+            url: None,
+        }
     }
 }
 
@@ -46,86 +121,192 @@ impl TryFrom<PathBuf> for InstallData {
     type Error = ParseError;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        let version = fs::read_to_string(path.join("version"))
-            .map_err(|_| ParseError::NoVersion)?
-            .chars()
-            .filter(|x| !x.is_whitespace())
-            .collect::<String>();
+        let version = parse_version(
+            &fs::read_to_string(path.join("version")).map_err(|_| ParseError::NoVersion)?,
+        )?;
 
-        Ok(Self { version })
+        let installed_at = fs::read_to_string(path.join("installed"))
+            .ok()
+            .and_then(|content| content.trim().parse::<u64>().ok())
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+
+        let depends = fs::read_to_string(path.join("depends"))
+            .ok()
+            .map(|content| {
+                content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(String::from)
+                    .collect()
+            });
+
+        Ok(Self {
+            version,
+            installed_at,
+            depends,
+        })
     }
 }
 
 impl Repo {
+    /// A remote repository, which is read from its mirror within the cache until
+    /// `pur update` downloads it again, see [remote::mirror].
+    pub fn remote(url: &str) -> Self {
+        let url = url.trim_end_matches('/');
+
+        Self {
+            dir: remote::mirror_dir(url),
+            url: Some(url.to_owned()),
+        }
+    }
+
+    /// The name of the repository, which is the name of its directory,
+    /// or the last part of its URL for a remote repository.
+    pub fn name(&self) -> String {
+        if let Some(url) = &self.url {
+            return url.rsplit('/').next().unwrap_or_default().to_owned();
+        }
+
+        self.dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// When the repository was last updated through `pur update`, if it ever was.
+    pub fn last_updated(&self) -> Option<SystemTime> {
+        fs::metadata(self.dir.join(LAST_UPDATE))
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    /// How many whole days ago the repository was last updated, see [Repo::last_updated].
+    pub fn days_since_update(&self, now: SystemTime) -> Option<u64> {
+        let elapsed = now
+            .duration_since(self.last_updated()?)
+            .unwrap_or(Duration::ZERO);
+
+        Some(elapsed.as_secs() / (24 * 60 * 60))
+    }
+
     /// This method fetches all packages from the local system, using the
     /// current repository as base directory.
     ///
     /// It will loop through every directory within the repository (not recursively),
     /// and it will attempt to add every directory to the return value as a Package.
+    /// Directories that aren't valid packages are skipped, see [Repo::scan] for why.
     ///
-    /// Every package will be re-fetched everytime this method is called, and not cached,
-    /// so it's recommended to not call this method every single time you need packages;
-    /// call it somewhere globally.
+    /// The package definitions are read from the index of the repository within the
+    /// [cache_dir] whenever it's fresh, see [index::load]. The packages are still parsed
+    /// every time this method is called, so it's recommended to not call this method every
+    /// single time you need packages; call it somewhere globally.
     pub fn get_packages(&self) -> std::io::Result<Vec<Package>> {
-        Ok(fs::read_dir(&self.dir)?
-            .into_iter()
-            .filter(|r| r.is_ok())
-            .map(|r| r.unwrap().path())
-            .flat_map(|x| Package::try_from(x))
-            .collect::<Vec<Package>>())
+        self.scan().map(|(packages, _)| packages)
+    }
+
+    /// Fetches all packages like [Repo::get_packages], together with the directories
+    /// that couldn't be parsed as a package and why, so they don't silently go missing.
+    ///
+    /// Files and hidden directories (e.g. `.git`) aren't packages, so they're left out.
+    pub fn scan(&self) -> std::io::Result<(Vec<Package>, Vec<InvalidPackage>)> {
+        self.scan_in(&cache_dir())
+    }
+
+    fn scan_in(&self, cache: &Path) -> std::io::Result<(Vec<Package>, Vec<InvalidPackage>)> {
+        let mut packages = Vec::<Package>::new();
+        let mut errors = Vec::<InvalidPackage>::new();
+
+        for definition in index::load(&self.dir, cache)? {
+            let path = definition.dir.clone();
+
+            match Package::try_from(definition) {
+                Ok(package) => packages.push(package),
+                Err(e) => errors.push((path, e)),
+            }
+        }
+
+        Ok((packages, errors))
+    }
+
+    /// Rebuilds the index of the repository, see [index::rebuild].
+    pub fn rebuild_index(&self) -> std::io::Result<()> {
+        index::rebuild(&self.dir, &cache_dir()).map(|_| ())
     }
 
     pub fn update_repository(
         &self,
         update_callback: &mut dyn FnMut(Package, InstallData) -> Result<(), UpdateError>,
     ) -> Result<(), UpdateError> {
-        let update_file = self.dir.join("update");
-        let current_dir = std::env::current_dir();
-
-        // if the update scrip doesn't exist, return early with an error.
-        if !update_file.exists() {
-            return Err(UpdateError::NoUpdateScript);
+        // a remote repository is downloaded as a whole, there's nothing to pull or run.
+        match &self.url {
+            Some(url) => remote::mirror(url, &self.dir)?,
+            None => self.update_local()?,
         }
 
-        set_current_dir(&self.dir).map_err(|_| UpdateError::NoUpdateScript)?;
+        // this isn't critical, the worst case is an unnecessary staleness warning.
+        let _ = fs::File::create(self.dir.join(LAST_UPDATE));
 
-        // call the update script as a command
-        Command::new(update_file.as_os_str())
-            .spawn()
-            .map_err(|_| UpdateError::UpdateScriptError)?
-            .wait_with_output()
+        // the update likely changed many packages, reading them all at once saves checking each.
+        self.rebuild_index()
             .map_err(|_| UpdateError::UpdateScriptError)?;
 
-        if let Ok(value) = current_dir {
-            set_current_dir(value).map_err(|_| UpdateError::UpdateScriptError)?;
-        }
-
         // here we want to update the packages themselves
         for (package, data) in self
             .get_packages()
             .map_err(|_| UpdateError::UpdateScriptError)?
             .iter()
-            .map(|package| {
-                let data = package.is_installed();
-
-                match data {
-                    Some(value) => Some((package, value)),
-                    None => None,
-                }
-            })
-            .flatten()
+            .filter_map(|package| package.is_installed().map(|data| (package, data)))
         {
-            let x = package.version.clone();
-            let y = data.version.clone();
+            // we can't tell whether a package without a version is newer or not.
+            if package.version.is_empty() || data.version.is_empty() {
+                continue;
+            }
+
+            if !is_update(&package.version, &data.version) {
+                continue;
+            }
+
+            update_callback(package.clone(), data.clone())?;
+        }
+
+        Ok(())
+    }
 
-            let cmp = comparse_version(&x, &y);
+    // Updates a repository directory, by pulling it if it's a git checkout,
+    // and by running its update script if it has one.
+    fn update_local(&self) -> Result<(), UpdateError> {
+        let update_file = self.dir.join(script_name(&self.dir, "update_script"));
+        let is_checkout = self.dir.join(".git").exists();
 
-            if let Ok(val) = cmp {
-                if val < 0 {
-                    continue;
-                }
+        // a repository that's neither a git checkout nor has an update script can't be updated.
+        if !is_checkout && !update_file.exists() {
+            return Err(UpdateError::NoUpdateScript);
+        }
+
+        // git checkouts are pulled first, so an update script can still do whatever else
+        // the repository needs afterwards, e.g. fetching something that isn't tracked.
+        if is_checkout {
+            git::pull(&self.dir)?;
+        }
 
-                update_callback(package.clone(), data.clone())?;
+        if update_file.exists() {
+            // call the update script as a command, from within the repository. That's only the
+            // directory of the script, ours is left alone for whatever else runs within this process.
+            // The script is resolved up front, as a relative path may be resolved from either.
+            let update_file =
+                std::path::absolute(&update_file).map_err(|_| UpdateError::NoUpdateScript)?;
+
+            let output = Command::new(update_file.as_os_str())
+                .current_dir(&self.dir)
+                .spawn()
+                .map_err(|_| UpdateError::UpdateScriptError)?
+                .wait_with_output()
+                .map_err(|_| UpdateError::UpdateScriptError)?;
+
+            // a script that failed may have left the repository half updated.
+            if !output.status.success() {
+                return Err(UpdateError::UpdateScriptError);
             }
         }
 
@@ -133,18 +314,131 @@ impl Repo {
     }
 }
 
-fn comparse_version(x: &str, y: &str) -> Result<i32, ParseIntError> {
-    let x = x
-        .chars()
-        .filter(|c| c.is_digit(10))
-        .collect::<String>()
-        .parse::<i32>()?;
+/// Whether the version within the repository is an update of the installed one, which it only
+/// is when it's strictly newer. Equal versions (including `1.0` and `1.0.0`) aren't updated.
+pub fn is_update(available: &Version, installed: &Version) -> bool {
+    available > installed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repos() -> Vec<Repo> {
+        [
+            "/usr/repo/pur",
+            "/usr/repo/pur-community",
+            "/usr/repo/unofficial",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .map(Repo::from)
+        .collect()
+    }
+
+    #[test]
+    fn splits_repositories() {
+        assert_eq!(
+            split_repositories("/usr/repo/pur:https://repo.example.com/pur:http://host:8080:/srv"),
+            [
+                "/usr/repo/pur",
+                "https://repo.example.com/pur",
+                "http://host:8080",
+                "/srv"
+            ]
+        );
+        assert_eq!(Repo::remote("https://repo.example.com/pur/").name(), "pur");
+    }
+
+    #[test]
+    fn precedence_reorders_repositories() {
+        let ordered = with_precedence(repos(), &["unofficial", "/usr/repo/pur-community"])
+            .unwrap()
+            .iter()
+            .map(Repo::name)
+            .collect::<Vec<String>>();
+
+        assert_eq!(ordered, ["unofficial", "pur-community", "pur"]);
+    }
+
+    #[test]
+    fn reads_install_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = || InstallData::try_from(dir.path().to_path_buf()).unwrap();
+
+        fs::write(dir.path().join("version"), "1.0").unwrap();
+        assert_eq!(data().installed_at, None);
+
+        // packages installed before the time was recorded have an empty marker.
+        fs::write(dir.path().join("installed"), "").unwrap();
+        assert_eq!(data().installed_at, None);
+
+        fs::write(dir.path().join("installed"), "1700000000\n").unwrap();
+        assert_eq!(
+            data().installed_at,
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+    }
+
+    #[test]
+    fn only_newer_versions_are_updates() {
+        let v = |s: &str| s.parse::<Version>().unwrap();
+
+        assert!(is_update(&v("1.10"), &v("1.9")));
+        assert!(!is_update(&v("1.9"), &v("1.10")));
+        assert!(!is_update(&v("1.0"), &v("1.0.0")));
+        assert!(!is_update(&v("1.0.0"), &v("1.0")));
+        assert!(!is_update(&v("2.01"), &v("2.1")));
+        assert!(is_update(&v("2.010"), &v("2.9")));
+    }
+
+    #[test]
+    fn scan_reports_invalid_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+
+        for name in ["valid", "unversioned", ".git"] {
+            fs::create_dir_all(path(name)).unwrap();
+            fs::write(path(name).join("depends"), "").unwrap();
+        }
 
-    let y = y
-        .chars()
-        .filter(|c| c.is_digit(10))
-        .collect::<String>()
-        .parse::<i32>()?;
+        fs::write(path("valid/version"), "1.0").unwrap();
+        fs::write(path(LAST_UPDATE), "").unwrap();
+
+        let cache = tempfile::tempdir().unwrap();
+        let (packages, errors) = Repo::from(dir.path().to_path_buf())
+            .scan_in(cache.path())
+            .unwrap();
+
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].name, "valid");
+        assert!(matches!(
+            errors.as_slice(),
+            [(path, ParseError::NoVersion)] if path.ends_with("unversioned")
+        ));
+    }
 
-    Ok(x - y)
+    #[test]
+    fn tracks_last_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repo::from(dir.path().to_path_buf());
+        let now = SystemTime::now();
+
+        assert_eq!(repo.days_since_update(now), None);
+
+        let stamp = fs::File::create(dir.path().join(LAST_UPDATE)).unwrap();
+        stamp
+            .set_modified(now - Duration::from_secs(45 * 24 * 60 * 60 + 60))
+            .unwrap();
+
+        assert_eq!(repo.days_since_update(now), Some(45));
+    }
+
+    #[test]
+    fn precedence_rejects_unknown_repositories() {
+        assert!(matches!(
+            with_precedence(repos(), &["pur", "nope"]),
+            Err(ExecuteError::UnknownRepository(name)) if name == "nope"
+        ));
+    }
 }
@@ -0,0 +1,229 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    thread,
+};
+
+// How many of the last lines a script wrote to stderr are kept, see [run_logged].
+const TAIL_LINES: usize = 10;
+
+/// Creates the command running a package script, with its output piped so it can be
+/// passed to [run_logged].
+///
+/// If `shell` is set, the script is invoked through `/bin/sh` instead of being executed
+/// directly. If `verbose` is set, the script is traced with `sh -x`, and `PUR_VERBOSE_SCRIPTS`
+/// is exported so scripts running their own interpreters can honor it as well.
+pub fn command(script: &Path, shell: bool, verbose: bool) -> Command {
+    let mut command = match shell || verbose {
+        true => {
+            let mut command = Command::new("/bin/sh");
+
+            if verbose {
+                command.arg("-x").env("PUR_VERBOSE_SCRIPTS", "1");
+            }
+
+            command.arg(script);
+            command
+        }
+        false => Command::new(script.as_os_str()),
+    };
+
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    command
+}
+
+// The variables that are kept within a sanitized environment.
+const KEPT_VARS: [&str; 3] = ["HOME", "TERM", "TMPDIR"];
+
+/// Replaces the environment of a command with a minimal one, so builds don't depend
+/// on whatever happens to be set in the environment pur was invoked from.
+///
+/// Only `HOME`, `TERM` and `TMPDIR` are kept, `PATH` is reset to the system directories,
+/// and the locale is set to `C`. This means `LD_*` variables and the like are all cleared.
+pub fn sanitize(command: &mut Command) {
+    command.env_clear();
+
+    for var in KEPT_VARS {
+        if let Some(value) = std::env::var_os(var) {
+            command.env(var, value);
+        }
+    }
+
+    command
+        .env("PATH", "/usr/bin:/bin:/usr/sbin:/sbin")
+        .env("LC_ALL", "C")
+        .env("LANG", "C");
+}
+
+/// Whether spawning a script failed because it couldn't be executed directly,
+/// which is either a missing execute bit (EACCES) or a missing shebang (ENOEXEC).
+pub fn is_exec_error(e: &io::Error) -> bool {
+    e.kind() == ErrorKind::PermissionDenied || e.raw_os_error() == Some(libc::ENOEXEC)
+}
+
+/// Waits for a script to finish, while copying its stdout and stderr into the log file
+/// at the given path, and to our own whenever `echo` is set.
+///
+/// The output is appended to the log, so the scripts of every build phase end up in the same log.
+/// Returns the exit status along with the last lines the script wrote to stderr,
+/// so a failure can be reported without digging through the log.
+pub fn run_logged(
+    mut child: Child,
+    log: &Path,
+    echo: bool,
+) -> io::Result<(ExitStatus, Vec<String>)> {
+    let log = File::options().create(true).append(true).open(log)?;
+    let err_log = log.try_clone()?;
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    // stderr is copied on its own thread, so neither of the pipes can fill up and block the script.
+    let err_thread = thread::spawn(move || match stderr {
+        Some(stderr) => tee(stderr, echo.then(io::stderr), err_log),
+        None => Ok(VecDeque::new()),
+    });
+
+    if let Some(stdout) = stdout {
+        tee(stdout, echo.then(io::stdout), log)?;
+    }
+
+    let tail = err_thread
+        .join()
+        .map_err(|_| io::Error::other("Couldn't copy script output"))??;
+
+    Ok((child.wait()?, tail.into()))
+}
+
+/// Rotates the log at the given path, so the next run writes to a fresh log,
+/// while keeping the previous logs as `<log>.1`, `<log>.2`, and so on.
+///
+/// At most `keep` logs are kept, including the fresh one, so older logs are deleted.
+/// Returns the amount of deleted logs.
+pub fn rotate_logs(log: &Path, keep: usize) -> io::Result<usize> {
+    let nth = |index: usize| match index {
+        0 => log.to_path_buf(),
+        _ => {
+            let mut name = log.as_os_str().to_owned();
+            name.push(format!(".{}", index));
+            PathBuf::from(name)
+        }
+    };
+
+    let logs = (0..)
+        .map(nth)
+        .take_while(|path| path.exists())
+        .collect::<Vec<PathBuf>>();
+
+    let mut deleted = 0;
+
+    // Every log moves up a spot, starting with the oldest so nothing gets overwritten,
+    // and every log that would end up beyond the logs we keep is deleted instead.
+    for (index, path) in logs.iter().enumerate().rev() {
+        if index + 1 >= keep {
+            fs::remove_file(path)?;
+            deleted += 1;
+        } else {
+            fs::rename(path, nth(index + 1))?;
+        }
+    }
+
+    Ok(deleted)
+}
+
+/// Deletes the log at the given path, and every rotated log of it.
+/// Returns the amount of deleted logs.
+pub fn purge_logs(log: &Path) -> io::Result<usize> {
+    rotate_logs(log, 0)
+}
+
+// Copies every line into the log and `to`, keeping the last few lines that were copied.
+fn tee(from: impl Read, mut to: Option<impl Write>, mut log: File) -> io::Result<VecDeque<String>> {
+    let mut reader = BufReader::new(from);
+    let mut line = Vec::<u8>::new();
+    let mut tail = VecDeque::with_capacity(TAIL_LINES);
+
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        if let Some(to) = &mut to {
+            to.write_all(&line)?;
+        }
+
+        log.write_all(&line)?;
+
+        if tail.len() == TAIL_LINES {
+            tail.pop_front();
+        }
+
+        tail.push_back(String::from_utf8_lossy(&line).trim_end().to_owned());
+        line.clear();
+    }
+
+    Ok(tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn logs_traced_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("install");
+        let log = dir.path().join("build.log");
+
+        fs::write(&script, "echo building\necho oops >&2\n").unwrap();
+
+        let child = command(&script, true, true).spawn().unwrap();
+        let (status, tail) = run_logged(child, &log, false).unwrap();
+        let log = fs::read_to_string(log).unwrap();
+
+        assert!(status.success());
+        assert!(tail.contains(&"oops".to_owned()));
+        assert!(log.contains("building\n"));
+        assert!(log.contains("oops\n"));
+        assert!(log.contains("+ echo building"));
+    }
+
+    #[test]
+    fn rotates_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("build.log");
+
+        for run in 0..4 {
+            rotate_logs(&log, 3).unwrap();
+            fs::write(&log, run.to_string()).unwrap();
+        }
+
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).ok();
+
+        assert_eq!(read("build.log").as_deref(), Some("3"));
+        assert_eq!(read("build.log.1").as_deref(), Some("2"));
+        assert_eq!(read("build.log.2").as_deref(), Some("1"));
+        assert_eq!(read("build.log.3"), None);
+
+        assert_eq!(purge_logs(&log).unwrap(), 3);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn sanitize_clears_environment() {
+        let mut command = Command::new("/bin/sh");
+        command
+            .arg("-c")
+            .arg("env")
+            .env("LD_PRELOAD", "/tmp/evil.so");
+
+        sanitize(&mut command);
+
+        let output = command.output().unwrap();
+        let env = String::from_utf8_lossy(&output.stdout);
+
+        assert!(!env.contains("LD_PRELOAD"));
+        assert!(env.contains("PATH=/usr/bin:/bin:/usr/sbin:/sbin"));
+        assert!(env.contains("LC_ALL=C"));
+    }
+}
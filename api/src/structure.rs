@@ -1,8 +1,19 @@
-use crate::error::FileStructureError;
-use std::{fs, path::PathBuf};
+use crate::{
+    config::{db_root, install_prefix, install_root},
+    error::FileStructureError,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ffi::{OsStr, OsString},
+    fs,
+    path::{Component, Path, PathBuf},
+};
 
 type FileResult<T> = Result<T, FileStructureError>;
 
+// The directories within the install prefix that are linked out of the file structure.
+const PREFIX_CHILDREN: [&str; 5] = ["bin", "lib", "lib64", "sbin", "linuxrc"];
+
 pub trait FileStructure: Sized {
     fn create_all(&self) -> FileResult<()>;
 
@@ -21,65 +32,315 @@ pub trait FileStructure: Sized {
     // children = [usr/bin, usr/lib]
     //
     // The children of this example will be /var/db/installed/pfetch/files/usr/bin etc..
-    // these children will then be moved to their base child path.
-    fn symlink_out_scope(&self) -> FileResult<()>;
+    // these children will then be moved to their base child path, within the install root.
+    //
+    // Returns the paths of every symlink that has been created.
+    fn symlink_out_scope(&self) -> FileResult<Vec<PathBuf>>;
 
     fn remove_symlinks(&self) -> FileResult<()>;
 
     // This method will move all of the current directories into
     // the target directory, while maintaining the correct structure
     // present within the current FileStructure.
-    fn move_all(&self, target: &PathBuf) -> FileResult<()>;
+    //
+    // Directories already present within the target are replaced.
+    fn move_all(&self, target: &Path) -> FileResult<()>;
 }
 
 #[derive(Debug, Clone)]
 pub struct InstallFileStructure {
-    id: String,
     parent: PathBuf,
-    children: Vec<String>,
+    children: Vec<PathBuf>,
+    link_dirs: Vec<PathBuf>,
 }
 
 impl InstallFileStructure {
     pub fn new(id: &str) -> Self {
-        let id = id.to_owned();
-        let parent = PathBuf::from(format!("/var/db/installed/{}/files", id));
+        Self::with_prefix(id, &install_prefix())
+    }
+
+    /// Creates the file structure of a package built for the given prefix,
+    /// a prefix of `/usr` results in the `usr/bin`, `usr/lib`, ... children.
+    pub fn with_prefix(id: &str, prefix: &Path) -> Self {
+        let parent = db_root().join(id).join("files");
+
+        // the children are relative to the files directory, so the root has to go.
+        let prefix = prefix
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect::<PathBuf>();
 
         Self {
-            id,
             parent,
-            children: vec!["usr/bin", "usr/lib", "usr/lib64", "usr/sbin", "usr/linuxrc"]
+            children: PREFIX_CHILDREN
                 .into_iter()
-                .map(String::from)
-                .collect::<Vec<String>>(),
+                .map(|child| prefix.join(child))
+                .collect::<Vec<PathBuf>>(),
+            link_dirs: Vec::new(),
         }
     }
 
+    /// Links the given directories as a whole, instead of linking every file within them.
+    /// This is meant for large trees of e.g. assets, where thousands of links would be wasteful.
+    ///
+    /// The directories are relative to the files directory like the children, e.g. `usr/lib/foo/data`,
+    /// and have to be within one of the children.
+    pub fn with_link_dirs(mut self, link_dirs: &[PathBuf]) -> Self {
+        self.link_dirs = link_dirs
+            .iter()
+            .map(|dir| {
+                dir.components()
+                    .filter(|c| matches!(c, Component::Normal(_)))
+                    .collect::<PathBuf>()
+            })
+            .collect();
+
+        self
+    }
+
+    /// Manages the given directories as well, e.g. `etc` or `usr/share`, so the files a package
+    /// installs there are linked out like those within the default children.
+    ///
+    /// The directories are relative to the files directory like the children. Those overlapping
+    /// a child (e.g. `usr/lib/foo` or `usr`) are left out, as their files would be linked twice.
+    pub fn with_paths(mut self, paths: &[PathBuf]) -> Self {
+        for path in paths {
+            let path = path
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .collect::<PathBuf>();
+
+            let overlaps = self
+                .children
+                .iter()
+                .any(|child| path.starts_with(child) || child.starts_with(&path));
+
+            if !path.as_os_str().is_empty() && !overlaps {
+                self.children.push(path);
+            }
+        }
+
+        self
+    }
+
+    /// The same file structure, within another parent directory,
+    /// e.g. the directory the package is being built in.
+    ///
+    /// A relative parent is resolved against the current directory right away,
+    /// so the structure keeps working when the current directory changes later on.
+    pub fn in_dir(&self, parent: &Path) -> Self {
+        Self {
+            parent: absolute(parent),
+            children: self.children.clone(),
+            link_dirs: self.link_dirs.clone(),
+        }
+    }
+
+    /// Every directory of the file structure, parents first, which is the order they're created in.
     pub fn get_path_bufs(&self) -> Vec<PathBuf> {
         let mut bufs = Vec::<PathBuf>::new();
         let parent = &self.parent;
 
+        // this should be here, so we can delete this directory whenever delete_all() is called.
+        bufs.push(parent.to_path_buf().parent().unwrap().to_path_buf());
+        bufs.push(parent.to_path_buf());
+
         for child in &self.children {
             bufs.push(parent.join(child));
         }
 
-        bufs.push(parent.to_path_buf());
+        // a directory always has less components than the directories within it,
+        // the sort is stable so the children keep their order.
+        bufs.sort_by_key(|path| path.components().count());
 
-        // this should be here, so we can delete this directory whenever delete_all() is called.
-        bufs.push(parent.to_path_buf().parent().unwrap().to_path_buf());
+        bufs
+    }
+
+    /// Every directory of the file structure, children first, which is the order they're deleted in.
+    pub fn deletion_order(&self) -> Vec<PathBuf> {
+        let mut bufs = self.get_path_bufs();
+        bufs.reverse();
+
+        bufs
+    }
+
+    /// Every file within the children of the file structure, together with the path it's
+    /// linked to within the given root, see [link_target]. The directories that are linked as
+    /// a whole (see [InstallFileStructure::with_link_dirs]) are included instead of their files.
+    ///
+    /// Both the files and the targets are absolute paths, whatever the current directory is.
+    pub fn links(&self, root: &Path) -> FileResult<Vec<(PathBuf, PathBuf)>> {
+        let root = absolute(root);
+        let mut links = Vec::<(PathBuf, PathBuf)>::new();
+
+        for (path, id) in self.get_children() {
+            if !path.exists() {
+                continue;
+            }
+
+            self.collect_links(&root, &path, &id, &path, &mut links)?;
+        }
+
+        Ok(links)
+    }
+
+    // Walks through a directory within a child, without descending into the directories
+    // which are linked as a whole. Symlinks to directories aren't followed.
+    fn collect_links(
+        &self,
+        root: &Path,
+        child: &Path,
+        id: &Path,
+        dir: &Path,
+        links: &mut Vec<(PathBuf, PathBuf)>,
+    ) -> FileResult<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let relative = relative_to(&path, child);
+            let is_dir = entry.file_type()?.is_dir();
+
+            if is_dir && !self.link_dirs.contains(&id.join(relative)) {
+                self.collect_links(root, child, id, &path, links)?;
+            } else if is_dir || path.is_file() {
+                let target_path = resolve_dirs(root, &link_target(root, id, relative)?)?;
+                links.push((path, target_path));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The links which collide with another file on a case-insensitive file system, e.g. `README`
+    /// and `readme`, together with the file they collide with. That's either a file that's already
+    /// within the root, or another link of the file structure.
+    ///
+    /// Only the directories on case-insensitive file systems are checked, see [is_case_insensitive].
+    pub fn case_collisions(&self, root: &Path) -> FileResult<Vec<(PathBuf, PathBuf)>> {
+        self.case_collisions_with(root, &is_case_insensitive)
+    }
+
+    fn case_collisions_with(
+        &self,
+        root: &Path,
+        insensitive: &dyn Fn(&Path) -> bool,
+    ) -> FileResult<Vec<(PathBuf, PathBuf)>> {
+        let mut collisions = Vec::<(PathBuf, PathBuf)>::new();
+        let mut checked = HashMap::<PathBuf, bool>::new();
+        let mut seen = HashMap::<PathBuf, PathBuf>::new();
+
+        for (_, target_path) in self.links(root)? {
+            let parent = match target_path.parent() {
+                Some(parent) => parent,
+                None => continue,
+            };
+
+            if !*checked
+                .entry(parent.to_path_buf())
+                .or_insert_with(|| insensitive(parent))
+            {
+                continue;
+            }
+
+            let folded = fold_case(&target_path);
+
+            if let Some(other) = seen.get(&folded) {
+                collisions.push((target_path, other.clone()));
+                continue;
+            }
+
+            seen.insert(folded.clone(), target_path.clone());
+
+            // our own links from a previous installation aren't in the way.
+            let existing = fs::read_dir(parent)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path != &target_path && fold_case(path) == folded)
+                .find(|path| !fs::read_link(path).is_ok_and(|file| file.starts_with(&self.parent)));
+
+            if let Some(existing) = existing {
+                collisions.push((target_path, existing));
+            }
+        }
+
+        Ok(collisions)
+    }
 
-        return bufs;
+    /// Creates the links of the file structure within the given root, see [FileStructure::symlink_out_scope].
+    ///
+    /// Links that are already there (e.g. from the previous version of the package) are kept,
+    /// and returned together with the created ones. Nothing is linked whenever a link collides
+    /// with another file on a case-insensitive file system, see [InstallFileStructure::case_collisions].
+    ///
+    /// Linking is all or nothing, whenever a link can't be created the ones created so far
+    /// are removed again, while the links that were already there are kept. Missing directories
+    /// within the root are created for the links, and kept around.
+    pub fn link_into(&self, root: &Path) -> FileResult<Vec<PathBuf>> {
+        let collisions = self.case_collisions(root)?;
+
+        if !collisions.is_empty() {
+            return Err(FileStructureError::CaseCollision(collisions));
+        }
+
+        let mut linked = Vec::<PathBuf>::new();
+        let mut created = Vec::<PathBuf>::new();
+
+        for (file, target_path) in self.links(root)? {
+            if fs::read_link(&target_path).ok() != Some(file.clone()) {
+                // directories like `usr/share/doc/<package>` don't exist before the package does.
+                let linked = match target_path.parent() {
+                    Some(parent) => fs::create_dir_all(parent),
+                    None => Ok(()),
+                }
+                .and_then(|_| symlink(&file, &target_path));
+
+                if let Err(e) = linked {
+                    for path in created {
+                        let _ = fs::remove_file(path);
+                    }
+
+                    return Err(FileStructureError::SymLinkError(format!(
+                        "couldn't link {}: {}",
+                        target_path.display(),
+                        e
+                    )));
+                }
+
+                created.push(target_path.clone());
+            }
+
+            linked.push(target_path);
+        }
+
+        Ok(linked)
+    }
+
+    /// Removes the links of the file structure from the given root, see [FileStructure::remove_symlinks].
+    ///
+    /// Links to directories are removed as links, the directories they point to are left alone.
+    /// Only the links pointing into the file structure are removed, see [remove_links_into].
+    pub fn unlink_from(&self, root: &Path) -> FileResult<()> {
+        let targets = self
+            .links(root)?
+            .into_iter()
+            .map(|(_, target_path)| target_path)
+            .collect::<Vec<PathBuf>>();
+
+        remove_links_into(&self.parent, &targets)
     }
 
-    pub fn get_children(&self) -> Vec<(PathBuf, String)> {
-        let mut children = Vec::<(PathBuf, String)>::new();
+    pub fn get_children(&self) -> Vec<(PathBuf, PathBuf)> {
+        let mut children = Vec::<(PathBuf, PathBuf)>::new();
         let parent = &self.parent;
 
         for child in &self.children {
             let path = parent.join(child);
-            children.push((path, child.to_owned()));
+            children.push((path, child.clone()));
         }
 
-        return children;
+        children
     }
 }
 
@@ -97,7 +358,7 @@ impl FileStructure for InstallFileStructure {
     }
 
     fn delete_all(&self) -> FileResult<()> {
-        for path in self.get_path_bufs() {
+        for path in self.deletion_order() {
             if !path.exists() {
                 continue;
             }
@@ -108,7 +369,7 @@ impl FileStructure for InstallFileStructure {
         Ok(())
     }
 
-    fn move_all(&self, target: &PathBuf) -> FileResult<()> {
+    fn move_all(&self, target: &Path) -> FileResult<()> {
         for (path, id) in self.get_children() {
             if !path.exists() {
                 continue;
@@ -118,103 +379,287 @@ impl FileStructure for InstallFileStructure {
             // the target directory.
             let target_path = target.join(id);
 
-            fs::copy(path, target_path)?;
+            if target_path.exists() {
+                fs::remove_dir_all(&target_path)?;
+            }
+
+            if let Some(parent) = target_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            // renaming doesn't work across file systems, so we have to copy in that case.
+            if fs::rename(&path, &target_path).is_err() {
+                copy_dir(&path, &target_path)?;
+                fs::remove_dir_all(&path)?;
+            }
         }
 
         Ok(())
     }
 
-    fn symlink_out_scope(&self) -> FileResult<()> {
-        for (path, id) in self.get_children() {
-            if !path.exists() {
-                continue;
-            }
+    fn symlink_out_scope(&self) -> FileResult<Vec<PathBuf>> {
+        self.link_into(&install_root())
+    }
 
-            let target_path = PathBuf::from(id).join(&self.id);
+    fn remove_symlinks(&self) -> FileResult<()> {
+        self.unlink_from(&install_root())
+    }
+}
 
-            // I'm not sure if this has to be done recursively, currently
-            // this is done recursively expecting there to be directories within the target
-            // directories (e.g usr/bin/data), but not sure if this should be expected behaviour.
-            do_recursive::<FileStructureError>(&path, &|path| {
-                let child = path
-                    .file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .split("/")
-                    .into_iter()
-                    .map(String::from)
-                    .collect::<Vec<String>>();
+/// Whether the file system a directory is on compares file names case-insensitively.
+///
+/// This doesn't write anything, the directory (or its closest existing ancestor with letters
+/// in its name) is looked up by its name in a different case instead.
+#[cfg(unix)]
+pub fn is_case_insensitive(dir: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
 
-                let last = child.get(child.len() - 2);
-                let mut target_path = PathBuf::from("/").join(target_path.clone());
+    for path in dir.ancestors() {
+        // names which aren't valid UTF-8 don't have a case we could swap.
+        let (meta, name) = match (fs::metadata(path), path.file_name().and_then(OsStr::to_str)) {
+            (Ok(meta), Some(name)) => (meta, name),
+            _ => continue,
+        };
 
-                if let Some(last) = last {
-                    target_path = target_path.join(&last);
-                }
+        let swapped = name
+            .chars()
+            .map(|c| match c.is_uppercase() {
+                true => c.to_lowercase().collect::<String>(),
+                false => c.to_uppercase().collect::<String>(),
+            })
+            .collect::<String>();
 
-                if path.is_file() {
-                    symlink(&path, &target_path)?
-                }
+        if swapped == name {
+            continue;
+        }
 
-                Ok(())
-            })?
+        return fs::metadata(path.with_file_name(swapped))
+            .is_ok_and(|other| other.dev() == meta.dev() && other.ino() == meta.ino());
+    }
+
+    false
+}
+
+// NTFS compares file names case-insensitively, unless it's been turned off per directory.
+#[cfg(target_os = "windows")]
+pub fn is_case_insensitive(_: &Path) -> bool {
+    true
+}
+
+// The path with its file name in lowercase, which is the same for every name that only differs in case.
+// Names which aren't valid UTF-8 are kept as they are, rather than mangling them into colliding names.
+fn fold_case(path: &Path) -> PathBuf {
+    match path.file_name().map(|name| (name, name.to_str())) {
+        Some((_, Some(name))) => path.with_file_name(name.to_lowercase()),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Removes the links at the given paths which point into the given directory, e.g. the files
+/// of a package. Whatever else is at those paths, e.g. a file another package replaced the link
+/// with, isn't ours to remove, so it's left alone. Paths that don't exist are skipped.
+///
+/// Every link is tried, the ones that couldn't be removed are reported together afterwards.
+pub fn remove_links_into(dir: &Path, paths: &[PathBuf]) -> FileResult<()> {
+    let dir = absolute(dir);
+    let mut failed = Vec::<(PathBuf, std::io::Error)>::new();
+
+    for path in paths {
+        if !fs::read_link(path).is_ok_and(|file| file.starts_with(&dir)) {
+            continue;
         }
 
-        Ok(())
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => failed.push((path.clone(), e)),
+            _ => {}
+        }
     }
 
-    fn remove_symlinks(&self) -> FileResult<()> {
-        for (path, id) in self.get_children() {
-            if !path.exists() {
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    let failed = failed
+        .iter()
+        .map(|(path, e)| format!("{} ({})", path.display(), e))
+        .collect::<Vec<String>>();
+
+    Err(FileStructureError::SymLinkError(format!(
+        "couldn't remove {} links: {}",
+        failed.len(),
+        failed.join(", ")
+    )))
+}
+
+// The path of a file within the structure child it was found in.
+fn relative_to<'a>(file: &'a Path, child: &Path) -> &'a Path {
+    file.strip_prefix(child).unwrap_or(file)
+}
+
+/// Computes where a file of a structure child is linked to within the install root,
+/// e.g. `usr/bin/pfetch` is linked to `<root>/usr/bin/pfetch`.
+///
+/// The target is normalized first, and rejected whenever it doesn't stay within the child
+/// it belongs to, so a package can't link files anywhere else through names like `../../etc/passwd`.
+pub fn link_target(root: &Path, child: &Path, relative: &Path) -> FileResult<PathBuf> {
+    let root = normalize(root);
+    let base = normalize(&root.join(child));
+    let target = normalize(&base.join(relative));
+
+    if base == root || !base.starts_with(&root) || target == base || !target.starts_with(&base) {
+        return Err(FileStructureError::PathEscape(
+            root.join(child).join(relative),
+        ));
+    }
+
+    Ok(target)
+}
+
+// The most symlinks followed while resolving a single path, like the kernel's limit.
+const MAX_SYMLINKS: usize = 40;
+
+// Resolves the symlinked directories on the way to a link target within the root, e.g. `usr/bin`
+// being a link to `/bin` on merged-/usr systems, so the link is created at the real location
+// (and found there again while removing it). The link itself isn't resolved.
+//
+// Absolute symlinks are resolved within the root rather than against `/`, as the root may be
+// staged somewhere else, and `..` never leaves the root.
+fn resolve_dirs(root: &Path, target: &Path) -> FileResult<PathBuf> {
+    let (parent, name) = match (target.parent(), target.file_name()) {
+        (Some(parent), Some(name)) => (parent, name),
+        _ => return Ok(target.to_path_buf()),
+    };
+
+    let mut pending = parent
+        .strip_prefix(root)
+        .unwrap_or(parent)
+        .components()
+        .map(|component| component.as_os_str().to_owned())
+        .collect::<VecDeque<OsString>>();
+    let mut resolved = root.to_path_buf();
+    let mut followed = 0;
+
+    while let Some(component) = pending.pop_front() {
+        if component == ".." {
+            if resolved != root {
+                resolved.pop();
+            }
+            continue;
+        }
+
+        if component == "." || component == "/" {
+            continue;
+        }
+
+        let next = resolved.join(&component);
+
+        let link = match fs::symlink_metadata(&next) {
+            Ok(meta) if meta.file_type().is_symlink() => fs::read_link(&next)?,
+            _ => {
+                resolved = next;
                 continue;
             }
+        };
 
-            let target_path = PathBuf::from(id).join(&self.id);
+        followed += 1;
 
-            do_recursive::<FileStructureError>(&path, &|path| {
-                let child = path
-                    .file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .split("/")
-                    .into_iter()
-                    .map(String::from)
-                    .collect::<Vec<String>>();
+        if followed > MAX_SYMLINKS {
+            return Err(FileStructureError::SymLinkError(format!(
+                "too many levels of symbolic links within {}",
+                target.display()
+            )));
+        }
 
-                let last = child.get(child.len() - 2);
-                let mut target_path = PathBuf::from("/").join(target_path.clone());
+        // the components of the link are walked before the rest of the path.
+        if link.is_absolute() {
+            resolved = root.to_path_buf();
+        }
 
-                if let Some(last) = last {
-                    target_path = target_path.join(&last);
-                }
+        for component in link.components().rev() {
+            pending.push_front(component.as_os_str().to_owned());
+        }
+    }
 
-                if path.is_file() {
-                    let _ = fs::remove_file(target_path);
-                }
+    Ok(resolved.join(name))
+}
+
+// Resolves a path against the current directory if it's relative, and normalizes it.
+fn absolute(path: &Path) -> PathBuf {
+    normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+// Lexically resolves `.` and `..` components, without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
 
-                Ok(())
-            })?
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
         }
+    }
 
-        Ok(())
+    normalized
+}
+
+// Copies a directory with all of its contents, symlinks are copied as symlinks.
+// The permissions of the files and directories are kept.
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let kind = entry.file_type()?;
+
+        if kind.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if kind.is_symlink() {
+            symlink(&fs::read_link(entry.path())?, &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
     }
+
+    // a directory that isn't writable is only locked down once everything is within it.
+    fs::set_permissions(to, fs::metadata(from)?.permissions())?;
+
+    Ok(())
 }
 
+/// Calls the callback for every file within the directory, at any depth.
+///
+/// Symlinks are followed, but every directory is only walked once, so a symlink
+/// pointing back at one of its parents doesn't loop.
 pub fn do_recursive<T>(
-    dir: &PathBuf,
-    callback: &dyn Fn(&PathBuf) -> Result<(), T>,
+    dir: &Path,
+    callback: &mut dyn FnMut(&Path) -> Result<(), T>,
+) -> Result<(), T> {
+    walk(dir, callback, &mut HashSet::new())
+}
+
+fn walk<T>(
+    dir: &Path,
+    callback: &mut dyn FnMut(&Path) -> Result<(), T>,
+    visited: &mut HashSet<DirId>,
 ) -> Result<(), T> {
-    for entry in dir.read_dir() {
-        for entry in entry {
-            let entry = entry.unwrap();
+    match dir_id(dir) {
+        Some(id) if visited.insert(id) => {}
+        _ => return Ok(()),
+    }
+
+    if let Ok(entries) = dir.read_dir() {
+        for entry in entries.flatten() {
             let path = entry.path();
 
+            // anything else, e.g. a dangling symlink or a socket, isn't a file we could call it for.
             match (path.is_file(), path.is_dir()) {
                 (true, false) => callback(&path)?,
-                (false, true) => do_recursive(dir, callback)?,
-                (_, _) => {
-                    println!("what? {:?}", path);
-                }
+                (false, true) => walk(&path, callback, visited)?,
+                (_, _) => {}
             }
         }
     }
@@ -222,13 +667,536 @@ pub fn do_recursive<T>(
     Ok(())
 }
 
-#[cfg(all(unix))]
-fn symlink(path: &PathBuf, target: &PathBuf) -> std::io::Result<()> {
-    std::os::unix::fs::symlink(&path, &target)
+// The directories that have been walked already are identified by their device and inode,
+// which are the same whatever path they're reached through.
+#[cfg(unix)]
+type DirId = (u64, u64);
+
+#[cfg(unix)]
+fn dir_id(dir: &Path) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(dir).ok().map(|meta| (meta.dev(), meta.ino()))
+}
+
+// the file index of a directory isn't available on stable, its canonical path has to do.
+#[cfg(target_os = "windows")]
+type DirId = PathBuf;
+
+#[cfg(target_os = "windows")]
+fn dir_id(dir: &Path) -> Option<DirId> {
+    fs::canonicalize(dir).ok()
+}
+
+#[cfg(unix)]
+fn symlink(path: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(path, target)
 }
 
 // this is just here to remove the stupid compile-time error on windows!
 #[cfg(target_os = "windows")]
-fn symlink(_: &PathBuf, _: &PathBuf) -> std::io::Result<()> {
+fn symlink(_: &Path, _: &Path) -> std::io::Result<()> {
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn children_follow_prefix() {
+        let usr = InstallFileStructure::with_prefix("pfetch", Path::new("/usr"));
+        let opt = InstallFileStructure::with_prefix("pfetch", Path::new("/opt/local"));
+
+        assert_eq!(
+            usr.children,
+            ["usr/bin", "usr/lib", "usr/lib64", "usr/sbin", "usr/linuxrc"].map(PathBuf::from)
+        );
+        assert_eq!(
+            opt.children,
+            [
+                "opt/local/bin",
+                "opt/local/lib",
+                "opt/local/lib64",
+                "opt/local/sbin",
+                "opt/local/linuxrc"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn deletes_children_before_parents() {
+        let structure = InstallFileStructure::with_prefix("pfetch", Path::new("/opt/local"));
+        let order = structure.deletion_order();
+
+        for (i, path) in order.iter().enumerate() {
+            assert!(
+                order[i + 1..].iter().all(|later| !later.starts_with(path)),
+                "{} is deleted before a directory within it",
+                path.display()
+            );
+        }
+
+        assert_eq!(
+            order.last(),
+            Some(&PathBuf::from("/var/db/installed/pfetch"))
+        );
+        assert_eq!(
+            structure.get_path_bufs()[0],
+            PathBuf::from("/var/db/installed/pfetch")
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let structure = structure.in_dir(&dir.path().join("pfetch/files"));
+
+        structure.create_all().unwrap();
+        assert!(dir.path().join("pfetch/files/opt/local/bin").is_dir());
+
+        structure.delete_all().unwrap();
+        assert!(!dir.path().join("pfetch").exists());
+    }
+
+    #[test]
+    fn moves_children_into_target() {
+        let build = tempfile::tempdir().unwrap();
+        let store = tempfile::tempdir().unwrap();
+
+        let structure =
+            InstallFileStructure::with_prefix("pfetch", Path::new("/usr")).in_dir(build.path());
+
+        fs::create_dir_all(build.path().join("usr/bin")).unwrap();
+        fs::write(build.path().join("usr/bin/pfetch"), "new").unwrap();
+        fs::write(build.path().join("scratch.o"), "").unwrap();
+
+        // the files of a previous build get replaced.
+        fs::create_dir_all(store.path().join("usr/bin")).unwrap();
+        fs::write(store.path().join("usr/bin/old"), "").unwrap();
+
+        structure.move_all(store.path()).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(store.path().join("usr/bin/pfetch")).unwrap(),
+            "new"
+        );
+        assert!(!store.path().join("usr/bin/old").exists());
+        assert!(!store.path().join("scratch.o").exists());
+        assert!(!build.path().join("usr/bin").exists());
+    }
+
+    #[test]
+    fn copies_nested_dirs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from");
+        let to = dir.path().join("to");
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        fs::create_dir_all(from.join("usr/lib/somepkg/plugins")).unwrap();
+        fs::create_dir_all(from.join("usr/bin")).unwrap();
+        fs::write(from.join("usr/bin/pfetch"), "#!/bin/sh\n").unwrap();
+        fs::write(from.join("usr/lib/somepkg/plugins/foo.so"), "foo").unwrap();
+        symlink(
+            Path::new("plugins/foo.so"),
+            &from.join("usr/lib/somepkg/foo.so"),
+        )
+        .unwrap();
+
+        fs::set_permissions(
+            from.join("usr/bin/pfetch"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        fs::set_permissions(
+            from.join("usr/lib/somepkg/plugins"),
+            fs::Permissions::from_mode(0o500),
+        )
+        .unwrap();
+
+        copy_dir(&from, &to).unwrap();
+
+        assert_eq!(mode(&to.join("usr/bin/pfetch")), 0o755);
+        assert_eq!(mode(&to.join("usr/lib/somepkg/plugins")), 0o500);
+        assert_eq!(
+            fs::read_to_string(to.join("usr/lib/somepkg/plugins/foo.so")).unwrap(),
+            "foo"
+        );
+        assert_eq!(
+            fs::read_link(to.join("usr/lib/somepkg/foo.so")).unwrap(),
+            Path::new("plugins/foo.so")
+        );
+
+        // the temporary directory has to be able to delete it again.
+        for root in [&from, &to] {
+            fs::set_permissions(
+                root.join("usr/lib/somepkg/plugins"),
+                fs::Permissions::from_mode(0o755),
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn links_are_independent_of_current_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+
+        fs::create_dir_all(dir.join("files/usr/bin")).unwrap();
+        fs::create_dir_all(dir.join("files/usr/lib")).unwrap();
+        fs::write(dir.join("files/usr/bin/pfetch"), "").unwrap();
+        fs::write(dir.join("files/usr/lib/libpfetch.so"), "").unwrap();
+
+        let structure = InstallFileStructure::with_prefix("pfetch", Path::new("/usr"));
+        let expected = structure
+            .in_dir(&dir.join("files"))
+            .links(&dir.join("root"))
+            .unwrap();
+
+        // the same directory relative to the current one, which is resolved once, up front,
+        // rather than whenever the structure is used.
+        let current = std::env::current_dir().unwrap();
+        let relative = "../"
+            .repeat(current.components().count() - 1)
+            .parse::<PathBuf>()
+            .unwrap()
+            .join(dir.strip_prefix("/").unwrap())
+            .join("files");
+        let links = structure
+            .in_dir(&relative)
+            .links(&dir.join("root"))
+            .unwrap();
+
+        assert!(relative.is_relative());
+        assert_eq!(links, expected);
+        assert!(expected.contains(&(
+            dir.join("files/usr/lib/libpfetch.so"),
+            dir.join("root/usr/lib/libpfetch.so")
+        )));
+        assert!(expected
+            .iter()
+            .all(|(file, target)| file.is_absolute() && target.is_absolute()));
+    }
+
+    #[test]
+    fn only_removes_own_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let root = dir.join("root");
+
+        fs::create_dir_all(dir.join("files/usr/bin")).unwrap();
+        fs::create_dir_all(dir.join("other/usr/bin")).unwrap();
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+
+        for name in ["a", "b", "c"] {
+            fs::write(dir.join("files/usr/bin").join(name), "").unwrap();
+        }
+
+        let structure = InstallFileStructure::with_prefix("pfetch", Path::new("/usr"))
+            .in_dir(&dir.join("files"));
+        structure.link_into(&root).unwrap();
+
+        // another package took over b, and c was replaced by a file.
+        fs::remove_file(root.join("usr/bin/b")).unwrap();
+        symlink(&dir.join("other/usr/bin/b"), &root.join("usr/bin/b")).unwrap();
+        fs::remove_file(root.join("usr/bin/c")).unwrap();
+        fs::write(root.join("usr/bin/c"), "").unwrap();
+
+        structure.unlink_from(&root).unwrap();
+
+        assert!(fs::symlink_metadata(root.join("usr/bin/a")).is_err());
+        assert_eq!(
+            fs::read_link(root.join("usr/bin/b")).unwrap(),
+            dir.join("other/usr/bin/b")
+        );
+        assert!(root.join("usr/bin/c").is_file());
+    }
+
+    #[test]
+    fn unwinds_links_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let root = dir.join("root");
+
+        fs::create_dir_all(dir.join("files/usr/bin")).unwrap();
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+
+        for name in ["a", "b", "c", "d"] {
+            fs::write(dir.join("files/usr/bin").join(name), "").unwrap();
+        }
+
+        // b is linked already, c is in the way.
+        symlink(&dir.join("files/usr/bin/b"), &root.join("usr/bin/b")).unwrap();
+        fs::write(root.join("usr/bin/c"), "someone else's").unwrap();
+
+        let structure = InstallFileStructure::with_prefix("pfetch", Path::new("/usr"))
+            .in_dir(&dir.join("files"));
+
+        assert!(matches!(
+            structure.link_into(&root),
+            Err(FileStructureError::SymLinkError(_))
+        ));
+
+        assert!(fs::symlink_metadata(root.join("usr/bin/a")).is_err());
+        assert!(fs::symlink_metadata(root.join("usr/bin/d")).is_err());
+        assert_eq!(
+            fs::read_link(root.join("usr/bin/b")).unwrap(),
+            dir.join("files/usr/bin/b")
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("usr/bin/c")).unwrap(),
+            "someone else's"
+        );
+    }
+
+    #[test]
+    fn links_through_symlinked_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let root = dir.join("root");
+
+        fs::create_dir_all(dir.join("files/usr/bin")).unwrap();
+        fs::create_dir_all(dir.join("files/usr/lib")).unwrap();
+        fs::write(dir.join("files/usr/bin/pfetch"), "").unwrap();
+        fs::write(dir.join("files/usr/lib/libpfetch.so"), "").unwrap();
+
+        // a merged-/usr root, where the absolute link is resolved within the root.
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::create_dir_all(root.join("usr/lib64")).unwrap();
+        symlink(Path::new("/bin"), &root.join("usr/bin")).unwrap();
+        symlink(Path::new("../usr/./lib64"), &root.join("usr/lib")).unwrap();
+
+        let structure = InstallFileStructure::with_prefix("pfetch", Path::new("/usr"))
+            .in_dir(&dir.join("files"));
+
+        let mut created = structure.link_into(&root).unwrap();
+        created.sort();
+
+        assert_eq!(
+            created,
+            [root.join("bin/pfetch"), root.join("usr/lib64/libpfetch.so")]
+        );
+        assert_eq!(
+            fs::read_link(root.join("bin/pfetch")).unwrap(),
+            dir.join("files/usr/bin/pfetch")
+        );
+
+        structure.unlink_from(&root).unwrap();
+
+        assert!(fs::symlink_metadata(root.join("bin/pfetch")).is_err());
+        assert!(fs::symlink_metadata(root.join("usr/lib64/libpfetch.so")).is_err());
+        assert!(fs::symlink_metadata(root.join("usr/bin")).is_ok());
+
+        // a link pointing at itself can't be resolved.
+        fs::remove_file(root.join("usr/bin")).unwrap();
+        symlink(Path::new("bin"), &root.join("usr/bin")).unwrap();
+
+        assert!(matches!(
+            structure.links(&root),
+            Err(FileStructureError::SymLinkError(_))
+        ));
+    }
+
+    #[test]
+    fn finds_case_collisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("files");
+        let root = dir.path().join("root");
+
+        for file in [
+            "usr/lib/doc/README",
+            "usr/lib/doc/readme",
+            "usr/lib/doc/LICENSE",
+        ] {
+            fs::create_dir_all(files.join(file).parent().unwrap()).unwrap();
+            fs::write(files.join(file), "").unwrap();
+        }
+
+        fs::create_dir_all(root.join("usr/lib/doc")).unwrap();
+        fs::write(root.join("usr/lib/doc/License"), "").unwrap();
+
+        let structure = InstallFileStructure::with_prefix("doc", Path::new("/usr")).in_dir(&files);
+        let doc = root.join("usr/lib/doc");
+
+        // nothing collides on a case-sensitive file system.
+        assert!(!is_case_insensitive(&doc));
+        assert!(structure.case_collisions(&root).unwrap().is_empty());
+
+        let mut collisions = structure
+            .case_collisions_with(&root, &|_| true)
+            .unwrap()
+            .into_iter()
+            .map(|(_, other)| other)
+            .collect::<Vec<PathBuf>>();
+        collisions.sort();
+
+        // one of README and readme is reported, depending on the order they're found in.
+        assert_eq!(collisions.len(), 2);
+        assert_eq!(collisions[0], doc.join("License"));
+        assert_eq!(fold_case(&collisions[1]), doc.join("readme"));
+    }
+
+    #[test]
+    fn links_extra_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("files");
+        let root = dir.path().join("root");
+
+        for file in ["usr/bin/foo", "usr/share/doc/foo/README", "etc/foo.conf"] {
+            fs::create_dir_all(files.join(file).parent().unwrap()).unwrap();
+            fs::write(files.join(file), "").unwrap();
+        }
+
+        let paths = ["/usr/share/", "etc", "usr/lib/foo", "usr"].map(PathBuf::from);
+        let structure = InstallFileStructure::with_prefix("foo", Path::new("/usr"))
+            .with_paths(&paths)
+            .in_dir(&files);
+
+        // the paths overlapping the default children are left out.
+        let children = structure
+            .get_children()
+            .into_iter()
+            .map(|(_, child)| child)
+            .collect::<Vec<PathBuf>>();
+
+        assert_eq!(
+            children[children.len() - 2..],
+            ["usr/share", "etc"].map(PathBuf::from)
+        );
+        assert!(!children.contains(&PathBuf::from("usr")));
+
+        let mut created = structure.link_into(&root).unwrap();
+        created.sort();
+
+        assert_eq!(
+            created,
+            [
+                root.join("etc/foo.conf"),
+                root.join("usr/bin/foo"),
+                root.join("usr/share/doc/foo/README"),
+            ]
+        );
+    }
+
+    #[test]
+    fn links_whole_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("files");
+        let root = dir.path().join("root");
+
+        for file in [
+            "usr/bin/game",
+            "usr/lib/libgame.so",
+            "usr/lib/game/assets/a.png",
+            "usr/lib/game/assets/maps/b.map",
+            "usr/lib/game/config",
+        ] {
+            fs::create_dir_all(files.join(file).parent().unwrap()).unwrap();
+            fs::write(files.join(file), "").unwrap();
+        }
+
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        fs::create_dir_all(root.join("usr/lib/game")).unwrap();
+
+        let structure = InstallFileStructure::with_prefix("game", Path::new("/usr"))
+            .with_link_dirs(&[PathBuf::from("/usr/lib/game/assets/")])
+            .in_dir(&files);
+
+        let mut created = structure.link_into(&root).unwrap();
+        created.sort();
+
+        assert_eq!(
+            created,
+            [
+                root.join("usr/bin/game"),
+                root.join("usr/lib/game/assets"),
+                root.join("usr/lib/game/config"),
+                root.join("usr/lib/libgame.so"),
+            ]
+        );
+
+        // the assets are reachable through the single directory link.
+        let assets = root.join("usr/lib/game/assets");
+        assert!(assets.symlink_metadata().unwrap().file_type().is_symlink());
+        assert!(assets.join("maps/b.map").is_file());
+
+        // linking again keeps the existing links.
+        let mut relinked = structure.link_into(&root).unwrap();
+        relinked.sort();
+        assert_eq!(relinked, created);
+
+        structure.unlink_from(&root).unwrap();
+
+        for path in &created {
+            assert!(path.symlink_metadata().is_err());
+        }
+
+        // only the links are gone, the files they pointed to are still there.
+        assert!(files.join("usr/lib/game/assets/maps/b.map").is_file());
+    }
+
+    #[test]
+    fn links_stay_within_child() {
+        let root = Path::new("/mnt/target");
+
+        assert_eq!(
+            link_target(root, Path::new("usr/bin"), Path::new("pfetch")).unwrap(),
+            PathBuf::from("/mnt/target/usr/bin/pfetch")
+        );
+        assert_eq!(
+            link_target(root, Path::new("usr/lib"), Path::new("./data/../libfoo.so")).unwrap(),
+            PathBuf::from("/mnt/target/usr/lib/libfoo.so")
+        );
+    }
+
+    #[test]
+    fn rejects_path_traversal() {
+        let root = Path::new("/mnt/target");
+
+        for relative in [
+            "../../etc/passwd",
+            "../../../../etc/passwd",
+            "..",
+            "bin/../../sbin/sh",
+        ] {
+            assert!(matches!(
+                link_target(root, Path::new("usr/bin"), Path::new(relative)),
+                Err(FileStructureError::PathEscape(_))
+            ));
+        }
+
+        assert!(link_target(root, Path::new("../../etc"), Path::new("passwd")).is_err());
+    }
+
+    #[test]
+    fn walks_nested_dirs_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+
+        fs::create_dir_all(path("usr/lib/somepkg/plugins")).unwrap();
+        fs::write(path("usr/bin-file"), "").unwrap();
+        fs::write(path("usr/lib/libfoo.so"), "").unwrap();
+        fs::write(path("usr/lib/somepkg/foo.so"), "").unwrap();
+        fs::write(path("usr/lib/somepkg/plugins/bar.so"), "").unwrap();
+        // a cyclic link back at a parent directory.
+        symlink(&path("usr"), &path("usr/lib/somepkg/plugins/loop")).unwrap();
+
+        let mut files = Vec::<PathBuf>::new();
+        do_recursive::<()>(&path("usr"), &mut |file| {
+            files.push(file.to_path_buf());
+            Ok(())
+        })
+        .unwrap();
+
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                path("usr/bin-file"),
+                path("usr/lib/libfoo.so"),
+                path("usr/lib/somepkg/foo.so"),
+                path("usr/lib/somepkg/plugins/bar.so"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use crate::error::ParseError;
+use std::{cmp::Ordering, fmt::Display, str::FromStr};
+
+/// A part of a version component, e.g. `2p1` consists of `2`, `p` and `1`.
+/// Numbers are always ordered before text.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Part {
+    Number(u64),
+    Text(String),
+}
+
+type Component = Vec<Part>;
+
+/// A parsed package version, formatted as `[epoch:][v]version[-pre-release][+build]`.
+///
+/// Versions are ordered by their epoch first, then by their dot separated components, which are
+/// compared numerically (so `1.10` is newer than `1.9`), where missing components count as zero
+/// (so `1.0` equals `1.0.0`). A pre-release is older than the release itself, `1.0-rc1` < `1.0`.
+/// A leading `v` (as in `v1.2.3`) and the build metadata are left out of the comparison, so
+/// `v1.0+git5` equals `1.0`.
+///
+/// The original string is kept as well, and is what's shown when the version is displayed.
+#[derive(Debug, Clone)]
+pub struct Version {
+    original: String,
+    epoch: u64,
+    components: Vec<Component>,
+    pre_release: Vec<Component>,
+    build: Option<String>,
+}
+
+impl Version {
+    /// The version as it was originally written.
+    pub fn as_str(&self) -> &str {
+        &self.original
+    }
+
+    /// The build metadata, e.g. the `git5` of `1.0+git5`.
+    pub fn build(&self) -> Option<&str> {
+        self.build.as_deref()
+    }
+
+    pub fn is_pre_release(&self) -> bool {
+        !self.pre_release.is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.original.is_empty()
+    }
+}
+
+impl FromStr for Version {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let original = s.trim().to_owned();
+
+        let (epoch, rest) = match original.split_once(':') {
+            Some((epoch, rest)) => (
+                epoch
+                    .parse::<u64>()
+                    .map_err(|_| ParseError::Other(format!("Invalid epoch in {}", original)))?,
+                rest,
+            ),
+            None => (0, original.as_str()),
+        };
+
+        // only a `v` followed by a number is a prefix, anything else is part of the version.
+        let rest = rest
+            .strip_prefix('v')
+            .filter(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or(rest);
+
+        let (rest, build) = match rest.split_once('+') {
+            Some((rest, build)) => (rest, Some(build.to_owned())),
+            None => (rest, None),
+        };
+
+        let (release, pre_release) = match rest.split_once('-') {
+            Some((release, pre_release)) => (release, parse_components(pre_release)),
+            None => (rest, Vec::new()),
+        };
+
+        Ok(Self {
+            epoch,
+            components: parse_components(release),
+            pre_release,
+            build,
+            original,
+        })
+    }
+}
+
+/// Parses the content of a `version` file, of a package definition as well as of the install
+/// database, which may contain whitespace such as a trailing newline.
+pub fn parse_version(content: &str) -> Result<Version, ParseError> {
+    content
+        .chars()
+        .filter(|x| !x.is_whitespace())
+        .collect::<String>()
+        .parse::<Version>()
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.original)
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| cmp_components(&self.components, &other.components))
+            .then_with(
+                // having no pre-release at all means it's the actual release,
+                // which comes after all of its pre-releases.
+                || match (self.pre_release.is_empty(), other.pre_release.is_empty()) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Greater,
+                    (false, true) => Ordering::Less,
+                    (false, false) => cmp_components(&self.pre_release, &other.pre_release),
+                },
+            )
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+/// A constraint on the version of a dependency, e.g. the `>=2.0` of a `libfoo>=2.0` line
+/// within a depends file. Versions are compared like they're ordered, so `=1.0` matches `1.0.0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq {
+    op: Op,
+    version: Version,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Greater,
+    GreaterEq,
+    Eq,
+    LessEq,
+    Less,
+}
+
+// The operators, the two character ones first so `>=` isn't read as `>`.
+const OPS: [(&str, Op); 5] = [
+    (">=", Op::GreaterEq),
+    ("<=", Op::LessEq),
+    (">", Op::Greater),
+    ("<", Op::Less),
+    ("=", Op::Eq),
+];
+
+impl VersionReq {
+    /// Whether the version satisfies the constraint.
+    pub fn matches(&self, version: &Version) -> bool {
+        let ordering = version.cmp(&self.version);
+
+        match self.op {
+            Op::Greater => ordering == Ordering::Greater,
+            Op::GreaterEq => ordering != Ordering::Less,
+            Op::Eq => ordering == Ordering::Equal,
+            Op::LessEq => ordering != Ordering::Greater,
+            Op::Less => ordering == Ordering::Less,
+        }
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        let (op, version) = OPS
+            .iter()
+            .find_map(|(symbol, op)| s.strip_prefix(symbol).map(|version| (*op, version)))
+            .ok_or_else(|| ParseError::Other(format!("Invalid version constraint {}", s)))?;
+
+        if version.trim().is_empty() {
+            return Err(ParseError::Other(format!(
+                "Version constraint {} lacks a version",
+                s
+            )));
+        }
+
+        Ok(Self {
+            op,
+            version: version.parse()?,
+        })
+    }
+}
+
+impl Display for VersionReq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = OPS
+            .iter()
+            .find(|(_, op)| *op == self.op)
+            .map(|(symbol, _)| *symbol)
+            .unwrap_or_default();
+
+        write!(f, "{}{}", symbol, self.version)
+    }
+}
+
+fn parse_components(s: &str) -> Vec<Component> {
+    s.split('.')
+        .filter(|component| !component.is_empty())
+        .map(parse_parts)
+        .collect()
+}
+
+// Splits a component into runs of digits and runs of anything else.
+fn parse_parts(component: &str) -> Component {
+    let mut parts = Component::new();
+    let mut chars = component.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        let digit = c.is_ascii_digit();
+        let mut run = String::new();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_ascii_digit() != digit {
+                break;
+            }
+
+            run.push(c);
+            chars.next();
+        }
+
+        parts.push(match digit {
+            // this can only fail for absurdly large numbers, which we compare as text instead.
+            true => run
+                .parse::<u64>()
+                .map(Part::Number)
+                .unwrap_or(Part::Text(run)),
+            false => Part::Text(run),
+        });
+    }
+
+    parts
+}
+
+fn cmp_components(x: &[Component], y: &[Component]) -> Ordering {
+    let zero = vec![Part::Number(0)];
+
+    for i in 0..x.len().max(y.len()) {
+        let x = x.get(i).unwrap_or(&zero);
+        let y = y.get(i).unwrap_or(&zero);
+
+        match x.cmp(y) {
+            Ordering::Equal => continue,
+            ordering => return ordering,
+        }
+    }
+
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn compares_numerically() {
+        assert!(v("1.10") > v("1.9"));
+        assert!(v("2.0") > v("1.99.99"));
+        assert!(v("0.0.1") < v("0.1"));
+    }
+
+    #[test]
+    fn ignores_leading_zeros() {
+        assert_eq!(v("1.01"), v("1.1"));
+        assert_eq!(v("01.002.0003"), v("1.2.3"));
+        assert!(v("1.010") > v("1.9"));
+    }
+
+    #[test]
+    fn missing_components_are_zero() {
+        assert_eq!(v("1.0"), v("1.0.0"));
+        assert_eq!(v("1"), v("1.0.0.0"));
+        assert!(v("1.0.1") > v("1.0"));
+    }
+
+    #[test]
+    fn epoch_takes_precedence() {
+        assert!(v("1:0.1") > v("9.9"));
+        assert!(v("2:1.0") > v("1:2.0"));
+        assert_eq!(v("0:1.0"), v("1.0"));
+        assert!("a:1.0".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn pre_release_is_older() {
+        assert!(v("1.0-rc1") < v("1.0"));
+        assert!(v("1.0-rc1") < v("1.0-rc2"));
+        assert!(v("1.0-alpha") < v("1.0-beta"));
+        assert!(v("1.0-rc1") > v("0.9"));
+    }
+
+    #[test]
+    fn strips_prefix_and_build_metadata() {
+        assert_eq!(v("v1.2.3"), v("1.2.3"));
+        assert_eq!(v("1:v2.0"), v("1:2.0"));
+        assert_eq!(v("1.0+git5"), v("1.0"));
+        assert!(v("v1.2.3-rc1+git5") < v("1.2.3"));
+
+        let version = parse_version("v1.2.3-rc1+git5\n").unwrap();
+        assert!(version.is_pre_release());
+        assert_eq!(version.build(), Some("git5"));
+        assert_eq!(version.as_str(), "v1.2.3-rc1+git5");
+
+        // a `v` that doesn't start a number is part of the version.
+        assert!(v("vim9") != v("9"));
+    }
+
+    #[test]
+    fn compares_mixed_components() {
+        assert!(v("2.3p2") > v("2.3p1"));
+        assert!(v("2.3p1") > v("2.3"));
+        assert!(v("1.2a") < v("1.10"));
+    }
+
+    #[test]
+    fn matches_constraints() {
+        let req = |s: &str| s.parse::<VersionReq>().unwrap();
+
+        assert!(req(">=2.0").matches(&v("2.0.0")));
+        assert!(req(">=2.0").matches(&v("2.10")));
+        assert!(!req(">=2.0").matches(&v("2.0-rc1")));
+        assert!(!req(">2.0").matches(&v("2.0")));
+        assert!(req("=1.0").matches(&v("1.0.0")));
+        assert!(req("<= 1.9").matches(&v("1.9")));
+        assert!(!req("<1.9").matches(&v("1.10")));
+
+        assert_eq!(req(">= 2.0").to_string(), ">=2.0");
+        assert!("~2.0".parse::<VersionReq>().is_err());
+        assert!(">=".parse::<VersionReq>().is_err());
+    }
+
+    #[test]
+    fn keeps_original() {
+        assert_eq!(v(" 1.0.0-rc1 ").as_str(), "1.0.0-rc1");
+        assert_eq!(v("1:2.0").to_string(), "1:2.0");
+    }
+}
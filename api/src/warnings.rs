@@ -0,0 +1,39 @@
+use std::sync::{Mutex, PoisonError};
+
+// Every warning of the current command, in the order they were emitted.
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Emits a non-fatal problem, e.g. a skipped package or an outdated repository.
+///
+/// The warning is printed right away, and collected so front-ends can show all of them again
+/// once the command is done (see [take]), as they're easily lost within build output otherwise.
+pub fn warn(message: impl Into<String>) {
+    let message = message.into();
+
+    println!("Warning: {}", message);
+    WARNINGS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(message);
+}
+
+/// Takes every warning emitted so far.
+pub fn take() -> Vec<String> {
+    std::mem::take(&mut *WARNINGS.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_warnings() {
+        warn("first");
+        warn(String::from("second"));
+
+        let warnings = take();
+
+        assert!(warnings.ends_with(&["first".to_owned(), "second".to_owned()]));
+        assert!(take().is_empty());
+    }
+}
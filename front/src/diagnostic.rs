@@ -0,0 +1,285 @@
+use api::config::{db_root, is_enabled, Config};
+use api::error::{BuildError, ExecuteError, ParseError, UpdateError};
+use std::fmt::Debug;
+use std::io::{self, IsTerminal};
+
+/// How bad an error is, which decides the color it's printed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Something failed, e.g. a build.
+    Error,
+    /// Something was skipped, but nothing is broken, e.g. a missing dependency.
+    Skipped,
+}
+
+/// An error that can be rendered for humans, with a short hint on how to fix it.
+pub trait Diagnostic: Debug {
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    /// What went wrong, as a sentence without a trailing period.
+    fn message(&self) -> String;
+
+    fn hint(&self) -> Option<String>;
+}
+
+impl Diagnostic for ExecuteError {
+    fn severity(&self) -> Severity {
+        match self {
+            Self::NoDependFound | Self::FileConflicts => Severity::Skipped,
+            _ => Severity::Error,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Self::NoDependFound => "a dependency couldn't be found".to_owned(),
+            Self::CompileFail => "a package failed to build".to_owned(),
+            Self::UninstallFail => "a package couldn't be removed".to_owned(),
+            Self::InvalidPackage => "a package is invalid".to_owned(),
+            Self::UnknownRepository(name) => format!("there's no repository called {}", name),
+            Self::FileConflicts => "files of the packages are in the way".to_owned(),
+            Self::CleanFail => "the build directories couldn't be removed".to_owned(),
+            Self::Aborted => "aborted, as going ahead wasn't confirmed".to_owned(),
+            Self::ChecksumFail => "the checksums couldn't be computed".to_owned(),
+            Self::DependencyCycle(cycle) => {
+                format!("the dependencies form a cycle: {}", cycle.join(" → "))
+            }
+            Self::PackageNotFound(names) => {
+                format!("there's no package called {}", names.join(", "))
+            }
+            Self::NotInstalled(names) => format!("{} isn't installed", names.join(", ")),
+            Self::Io(e) => e.clone(),
+            Self::RequiredBy {
+                package,
+                dependents,
+            } => format!("{} is required by {}", package, dependents.join(", ")),
+        }
+    }
+
+    fn hint(&self) -> Option<String> {
+        let hint = match self {
+            Self::NoDependFound => {
+                "run pur update, or check whether PUR_PATH contains every repository"
+            }
+            Self::CompileFail => {
+                return Some(format!(
+                    "check the build.log of the package within {}",
+                    db_root().display()
+                ))
+            }
+            Self::UninstallFail => "run pur doctor to look for interrupted removals",
+            Self::InvalidPackage => "fix the problems listed above",
+            Self::UnknownRepository(_) => "run pur config to see the configured repositories",
+            Self::FileConflicts => "use --force to overwrite the files, or --backup to keep them",
+            Self::CleanFail => "are you root?",
+            Self::Aborted => "pass --yes to go ahead without being asked, e.g. from scripts",
+            Self::ChecksumFail => "check whether every source within the sources file is reachable",
+            Self::DependencyCycle(_) => {
+                "remove one of the dependencies within the cycle from its depends file"
+            }
+            Self::PackageNotFound(_) => {
+                "check the spelling, or run pur update to fetch the latest packages"
+            }
+            Self::NotInstalled(_) => {
+                "run pur list to see what's installed, or pass --ignore-not-installed to skip them"
+            }
+            Self::Io(_) => "check the permissions of the files involved, or run pur as root",
+            Self::RequiredBy { dependents, .. } => {
+                return Some(format!(
+                    "remove {} as well, or use --force to remove it anyway",
+                    dependents.join(", ")
+                ))
+            }
+        };
+
+        Some(hint.to_owned())
+    }
+}
+
+impl Diagnostic for ParseError {
+    fn severity(&self) -> Severity {
+        match self {
+            Self::AlreadyInstalled | Self::NotInstalled => Severity::Skipped,
+            _ => Severity::Error,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Self::NoVersion => "the package doesn't have a version".to_owned(),
+            Self::NoDirectory(e) => format!("the package directory couldn't be read: {}", e),
+            Self::AlreadyInstalled => "the package is already installed".to_owned(),
+            Self::NotInstalled => "the package isn't installed".to_owned(),
+            Self::NoInstallScript { package, .. } => {
+                format!("{} doesn't have an install script", package)
+            }
+            Self::InstallScriptNotExecutable { package, .. } => {
+                format!("the install script of {} isn't executable", package)
+            }
+            Self::FailedInstallScript(tail) if tail.is_empty() => {
+                "the install script failed".to_owned()
+            }
+            // the last lines it wrote to stderr, one per line below the message.
+            Self::FailedInstallScript(tail) => format!(
+                "the install script failed:{}",
+                tail.iter()
+                    .map(|line| format!("\n  | {}", line))
+                    .collect::<String>()
+            ),
+            Self::NoDepends => "the package doesn't have a depends file".to_owned(),
+            Self::ReadOnlyDatabase(e) => format!("the install database is read-only: {}", e),
+            Self::InvalidName(path) => {
+                format!("the name of {} isn't valid UTF-8", path.display())
+            }
+            Self::InvalidSource { line, reason } => {
+                format!("line {} of the sources file is invalid: {}", line, reason)
+            }
+            Self::InvalidDepend { line, reason } => {
+                format!("line {} of the depends file is invalid: {}", line, reason)
+            }
+            Self::ChecksumMismatch {
+                file,
+                expected,
+                got,
+            } => format!(
+                "the checksum of {} doesn't match, expected {} but got {}",
+                file, expected, got
+            ),
+            Self::Other(e) => e.clone(),
+        }
+    }
+
+    fn hint(&self) -> Option<String> {
+        match self {
+            Self::NoVersion => Some("add a version file to the package".to_owned()),
+            Self::NoDepends => {
+                Some("add a depends file to the package, it may be empty".to_owned())
+            }
+            Self::NoInstallScript { path, .. } => {
+                Some(format!("add an install script at {}", path.display()))
+            }
+            Self::InstallScriptNotExecutable { path, .. } => Some(format!(
+                "run `chmod +x {}` to make it executable",
+                path.display()
+            )),
+            Self::FailedInstallScript(_) => Some(format!(
+                "check the build.log of the package within {}",
+                db_root().display()
+            )),
+            Self::ChecksumMismatch { file, .. } => Some(format!(
+                "{} changed since its checksum was recorded, run pur checksum on the package if that's expected",
+                file
+            )),
+            Self::ReadOnlyDatabase(_) => {
+                Some("run pur as root, or remount the install database read-write".to_owned())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Diagnostic for BuildError {
+    fn message(&self) -> String {
+        match self {
+            Self::LinkError => "the files of the package couldn't be linked".to_owned(),
+            Self::ReadOnlyDatabase(e) => format!("the install database is read-only: {}", e),
+            Self::FileConflict { path, owner } => {
+                format!("{} belongs to {}", path.display(), owner)
+            }
+        }
+    }
+
+    fn hint(&self) -> Option<String> {
+        match self {
+            Self::LinkError => Some("check for files in the way of the links".to_owned()),
+            Self::FileConflict { owner, .. } => Some(format!(
+                "use --force to overwrite the file of {}, or --backup to keep it",
+                owner
+            )),
+            Self::ReadOnlyDatabase(_) => {
+                Some("run pur as root, or remount the install database read-write".to_owned())
+            }
+        }
+    }
+}
+
+impl Diagnostic for UpdateError {
+    fn severity(&self) -> Severity {
+        match self {
+            Self::NoUpdateScript => Severity::Skipped,
+            _ => Severity::Error,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Self::NoUpdateScript => "the repository can't be updated".to_owned(),
+            Self::UpdateScriptError => "the update script of the repository failed".to_owned(),
+            Self::PackageUpdateError(e) => format!("a package couldn't be updated: {}", e),
+            // what git said, one line per line below the message.
+            Self::GitPullError(e) => format!(
+                "pulling the repository failed:{}",
+                e.lines()
+                    .map(|line| format!("\n  | {}", line))
+                    .collect::<String>()
+            ),
+            Self::MergeConflict(files) => format!(
+                "the pulled changes conflict with the local changes to {}",
+                files.join(", ")
+            ),
+            Self::FetchError(e) => format!("the remote repository couldn't be downloaded: {}", e),
+        }
+    }
+
+    fn hint(&self) -> Option<String> {
+        match self {
+            Self::NoUpdateScript => Some(
+                "the repository is neither remote, a git checkout nor has an update script"
+                    .to_owned(),
+            ),
+            Self::GitPullError(_) => Some(
+                "check the upstream branch of the repository, and whether it's reachable"
+                    .to_owned(),
+            ),
+            Self::FetchError(_) => Some(
+                "check that the URL of the repository is https://, and that it serves an index"
+                    .to_owned(),
+            ),
+            Self::MergeConflict(_) => Some(
+                "commit or reset the local changes within the repository, then update again"
+                    .to_owned(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Prints an error with its hint, colored by its severity when --color-diagnostics
+/// (the `color_diagnostics` setting) is set, or `auto` and stdout is a terminal.
+pub fn report(e: &dyn Diagnostic) {
+    let (label, color) = match e.severity() {
+        Severity::Error => ("error", "31"),
+        Severity::Skipped => ("skipped", "33"),
+    };
+
+    let colored = colored();
+    let paint = |code: &str, text: &str| match colored {
+        true => format!("\x1b[{}m{}\x1b[0m", code, text),
+        false => text.to_owned(),
+    };
+
+    println!("{}: {}", paint(color, label), e.message());
+
+    if let Some(hint) = e.hint() {
+        println!("  {}: {}", paint("36", "hint"), hint);
+    }
+}
+
+fn colored() -> bool {
+    match Config::load().value("color_diagnostics") {
+        "auto" => io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        value => is_enabled(value),
+    }
+}
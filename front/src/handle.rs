@@ -1,35 +1,271 @@
-use api::error::{ExecuteError, UpdateError};
-use api::package::Package;
-use api::repo::Repo;
-
-pub fn build(package: &Package, packages: &Vec<Package>) -> Result<(), ExecuteError> {
-    for ele in &package.depends {
-        let depend = packages.iter().find(|package| &package.name == ele);
-
-        match depend {
-            // We just want to call this method recursively until all dependencies are installed.
-            // We probably want to manually handle the error in here, considering they're children, and not the entire
-            // build process should have to be stopped just because this build fails.
-            Some(package) => install(&package, &packages)?,
-            // I'm not sure what kind of behaviour we should be expecting here.
-            // Should we expect the whole package to be skipped? Or should we just ignore this dependency?
-            // I suggest we completely skip the package for now, because there is simply something wrong with the package if
-            // the dependency is not present, and if it actually does depend on the package, there's something wrong with
-            // the user's repositories setup on their local system.
-            None => {
+use api::config::{config_path, Config, Source};
+use api::error::{ExecuteError, ParseError, UpdateError};
+use api::package::{Depend, Package};
+use api::repo::{InstallData, Repo};
+use api::version::Version;
+use api::warnings::warn;
+use clap::ArgMatches;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::SystemTime;
+
+use crate::diagnostic::report;
+use crate::timing;
+use crate::trace::Trace;
+
+/// Finds the package the user is referring to by name.
+///
+/// Names containing a `/` (e.g `./mypackage`) are treated as paths to a local package directory,
+/// which is parsed as an ad-hoc package that doesn't have to be in any repository. This is useful
+/// while working on a package before publishing it, its dependencies are still found in the repositories.
+pub fn find_package(name: &str, packages: &[Package]) -> Option<Package> {
+    if !name.contains('/') {
+        return find_named(name, packages);
+    }
+
+    // we want the real name of the directory, e.g for `.` or `mypackage/`.
+    let dir = PathBuf::from(name)
+        .canonicalize()
+        .unwrap_or_else(|_| PathBuf::from(name));
+
+    match Package::try_from(dir) {
+        Ok(package) => Some(package),
+        Err(e) => {
+            warn(format!(
+                "couldn't read local package {}, skipping it: {:?}",
+                name, e
+            ));
+            None
+        }
+    }
+}
+
+/// Finds the packages the user is referring to, through `find` (e.g. [find_package]).
+///
+/// Every name is looked up before failing, so the error lists every name that didn't match
+/// any package at once, e.g. a single typo within a list of packages.
+pub fn find_all<'a>(
+    names: impl Iterator<Item = &'a String>,
+    packages: &[Package],
+    find: fn(&str, &[Package]) -> Option<Package>,
+) -> Result<Vec<Package>, ExecuteError> {
+    let mut found = Vec::<Package>::new();
+    let mut missing = Vec::<String>::new();
+
+    for name in names {
+        match find(name, packages) {
+            Some(package) => found.push(package),
+            None => missing.push(name.clone()),
+        }
+    }
+
+    if !missing.is_empty() {
+        for name in &missing {
+            println!("package '{}' not found in any repository", name);
+        }
+
+        return Err(ExecuteError::PackageNotFound(missing));
+    }
+
+    Ok(found)
+}
+
+/// Finds a package by its name, or else by one of its aliases (see [Package::aliases]),
+/// in which case the user is told which package the alias stands for.
+pub fn find_named(name: &str, packages: &[Package]) -> Option<Package> {
+    if let Some(package) = packages.iter().find(|package| package.name == name) {
+        return Some(package.clone());
+    }
+
+    match packages
+        .iter()
+        .find(|package| package.aliases.iter().any(|alias| alias == name))
+    {
+        Some(package) => {
+            println!("{} is an alias of {}", name, package.name);
+            Some(package.clone())
+        }
+        None => None,
+    }
+}
+
+/// What installing packages did, see [install].
+#[derive(Debug, Default)]
+pub struct InstallReport {
+    /// The packages that were built, or fetched as a prebuilt binary.
+    pub built: Vec<String>,
+    pub installed: Vec<String>,
+    /// The packages that didn't have to be built, as they already were.
+    pub skipped: Vec<String>,
+    /// The packages that were left alone, as they're installed at their current version already.
+    pub unchanged: Vec<String>,
+    /// The packages that failed, in the order they failed. A package whose dependency
+    /// failed fails with the same error, right after its dependency.
+    pub failed: Vec<(String, ExecuteError)>,
+}
+
+impl InstallReport {
+    /// Whether anything was built or installed, e.g. for --report-changes.
+    pub fn changed(&self) -> bool {
+        !self.built.is_empty() || !self.installed.is_empty()
+    }
+}
+
+// Records that the package failed, and returns the error to fail with.
+fn failed(summary: &mut InstallReport, package: &Package, e: ExecuteError) -> ExecuteError {
+    summary.failed.push((package.name.clone(), e.clone()));
+    e
+}
+
+/// Builds the package, after installing its dependencies in the order they depend on each other.
+/// The package itself is always built, even if it's built already, but isn't installed.
+pub fn build(package: &Package, packages: &[Package]) -> Result<(), ExecuteError> {
+    let targets = [package.clone()];
+    let mut summary = InstallReport::default();
+    let order = resolve_for(package, packages, &mut summary)?;
+
+    for depend in order.iter().filter(|depend| depend.name != package.name) {
+        install_resolved(depend, &targets, &order, &mut summary)
+            .map_err(|e| failed(&mut summary, package, e))?;
+    }
+
+    finish_build(
+        package,
+        timing::build(package, || package.build()),
+        &mut summary,
+    )
+}
+
+// Resolves the order the target and its dependencies are installed in, see [resolve_order].
+// Whenever that fails, the package it failed at fails, followed by every package that led to it.
+fn resolve_for(
+    target: &Package,
+    packages: &[Package],
+    summary: &mut InstallReport,
+) -> Result<Vec<Package>, ExecuteError> {
+    let (e, chain) = match resolve(std::slice::from_ref(target), packages) {
+        Ok(order) => return Ok(order),
+        Err(failure) => failure,
+    };
+
+    match &e {
+        ExecuteError::DependencyCycle(cycle) => println!(
+            "Skipping build of {}, its dependencies form a cycle: {}",
+            target.name,
+            cycle.join(" → ")
+        ),
+        _ => println!(
+            "Skipping build of {}, failed to install one or several dependencies.",
+            target.name
+        ),
+    }
+
+    // a package that failed for another target isn't reported twice.
+    for name in chain.iter().rev() {
+        if !summary.failed.iter().any(|(failed, _)| failed == name) {
+            summary.failed.push((name.clone(), e.clone()));
+        }
+    }
+
+    Err(e)
+}
+
+// Reports how building a package went, falling back to its prebuilt binary if it failed and
+// --fallback-binary is set. The build itself may have run on another thread, see [build_concurrently].
+fn finish_build(
+    package: &Package,
+    built: Result<(), ParseError>,
+    summary: &mut InstallReport,
+) -> Result<(), ExecuteError> {
+    match built {
+        Ok(_) => {
+            println!("Built {} v{}", package.name, package.version);
+            println!("pur install {} to create symlinks.", package.name);
+            summary.built.push(package.name.clone());
+        }
+        // With --fallback-binary, a package that failed to build can still be
+        // installed from its prebuilt artifact, if it provides one.
+        Err(e) if package.binary().is_some() && Config::load().flag("fallback_binary") => {
+            println!(
+                "Failed to build {} v{}, using its prebuilt binary instead",
+                package.name, package.version
+            );
+
+            report(&e);
+
+            if let Err(e) = timing::build(package, || package.build_binary()) {
                 println!(
-                    "Skipping build of {}, failed to install one or several dependencies.",
-                    &package.name
+                    "Failed to fetch the prebuilt binary of {} v{}... Skipping!",
+                    package.name, package.version
                 );
-                return Err(ExecuteError::NoDependFound);
+
+                report(&e);
+
+                return Err(failed(summary, package, ExecuteError::CompileFail));
             }
+
+            println!("Fetched {} v{}", package.name, package.version);
+            summary.built.push(package.name.clone());
+        }
+        Err(e) => {
+            println!(
+                "Failed to build {} v{}... Skipping!",
+                package.name, package.version
+            );
+
+            report(&e);
+
+            return Err(failed(summary, package, ExecuteError::CompileFail));
         }
+    };
+
+    Ok(())
+}
+
+/// Builds a package into the given directory, leaving the install database alone.
+///
+/// Its dependencies aren't installed for it, as that would touch the install database,
+/// so they have to be installed beforehand.
+pub fn build_to(
+    package: &Package,
+    packages: &[Package],
+    output: &Path,
+) -> Result<(), ExecuteError> {
+    let trace = Trace::new("building", package);
+    let installed = |depend: &Depend| {
+        packages
+            .iter()
+            .find(|package| package.name == depend.name)
+            .and_then(|package| package.is_installed())
+            .map(|data| data.version)
+    };
+
+    for depend in &package.depends {
+        let found = match installed(depend) {
+            Some(version) if depend.is_satisfied_by(&version) => continue,
+            Some(version) => format!("found {}, doesn't satisfy it", version),
+            None => "NOT INSTALLED".to_owned(),
+        };
+
+        trace.fail(&format!("requires {} → {}", depend, found));
+        println!(
+            "Skipping build of {}, {} has to be installed first.",
+            package.name, depend
+        );
+        return Err(ExecuteError::NoDependFound);
     }
 
-    match package.build() {
+    match package.build_to(output) {
         Ok(_) => {
-            println!("Built {} v{}", package.name, package.version);
-            println!("pur install {} to create symlinks.", package.name);
+            println!(
+                "Built {} v{} into {}",
+                package.name,
+                package.version,
+                output.display()
+            );
         }
         Err(e) => {
             println!(
@@ -37,9 +273,7 @@ pub fn build(package: &Package, packages: &Vec<Package>) -> Result<(), ExecuteEr
                 package.name, package.version
             );
 
-            // Here we want to print the error for easier debugging.
-            // Should we only print this if a certain environment variable is set? (e.g DEBUG).
-            println!("{:?}", e);
+            report(&e);
 
             return Err(ExecuteError::CompileFail);
         }
@@ -48,14 +282,112 @@ pub fn build(package: &Package, packages: &Vec<Package>) -> Result<(), ExecuteEr
     Ok(())
 }
 
-pub fn install(package: &Package, packages: &Vec<Package>) -> Result<(), ExecuteError> {
-    if package.is_built().is_none() {
-        build(package, packages)?;
+/// Installs the packages together with their dependencies, building whatever isn't built yet.
+///
+/// A package that fails doesn't stop the others from being installed, everything that
+/// happened is reported instead, see [install_summary] for printing it.
+///
+/// With --jobs (the `jobs` setting) above 1, the packages that have to be built are built
+/// concurrently first, see [build_concurrently].
+pub fn install(targets: &[Package], packages: &[Package]) -> InstallReport {
+    let mut summary = InstallReport::default();
+    let config = Config::load();
+    let jobs = config.value("jobs").parse::<usize>().unwrap_or(1);
+
+    // the changes of a script can't be told apart from those of the builds running alongside it.
+    if jobs > 1 && !config.flag("audit_scripts") {
+        build_concurrently(targets, packages, jobs, &mut summary);
+    }
+
+    // the targets are installed one after another, each after its dependencies,
+    // so a target failing to resolve doesn't keep the others from being installed.
+    for target in targets {
+        let order = match resolve_for(target, packages, &mut summary) {
+            Ok(order) => order,
+            Err(_) => continue,
+        };
+
+        for package in &order {
+            // the failure is within the report already.
+            let _ = install_resolved(package, targets, &order, &mut summary);
+        }
+    }
+
+    summary
+}
+
+// Returns the error the package failed with, if it failed already.
+fn failure_of(summary: &InstallReport, name: &str) -> Option<ExecuteError> {
+    summary
+        .failed
+        .iter()
+        .find(|(failed, _)| failed == name)
+        .map(|(_, e)| e.clone())
+}
+
+// Installs a package of a resolved order, of which its dependencies come before it, so they're
+// installed (or failed) by now. Building it is skipped whenever it's built at its current version.
+fn install_resolved(
+    package: &Package,
+    targets: &[Package],
+    order: &[Package],
+    summary: &mut InstallReport,
+) -> Result<(), ExecuteError> {
+    // a dependency shared by several packages only has to be installed once,
+    // and isn't tried again after it failed.
+    if summary.installed.contains(&package.name) {
+        return Ok(());
     }
 
-    match package.install() {
+    if let Some(e) = failure_of(summary, &package.name) {
+        return Err(e);
+    }
+
+    // a package fails with the dependency that failed, right after it.
+    if let Some(e) = package
+        .depends
+        .iter()
+        .find_map(|depend| failure_of(summary, &depend.name))
+    {
+        return Err(failed(summary, package, e));
+    }
+
+    let upgrade_deps = Config::load().flag("upgrade_deps");
+
+    // one that was kept at its installed version is upgraded after all
+    // when another target requires a version it doesn't satisfy.
+    if package
+        .is_installed()
+        .is_some_and(|data| kept_in_order(package, &data.version, targets, order, upgrade_deps))
+    {
+        if !summary.unchanged.contains(&package.name) {
+            summary.unchanged.push(package.name.clone());
+        }
+
+        return Ok(());
+    }
+
+    summary.unchanged.retain(|name| name != &package.name);
+
+    // an older build, e.g. of a dependency that's upgraded to satisfy a constraint, is rebuilt.
+    match package.is_built() {
+        Some(built) if built.version == package.version => {
+            summary.skipped.push(package.name.clone())
+        }
+        _ => finish_build(package, timing::build(package, || package.build()), summary)?,
+    }
+
+    link(package, summary)
+}
+
+// Installs a package that's built, i.e. links its files into the install root.
+fn link(package: &Package, summary: &mut InstallReport) -> Result<(), ExecuteError> {
+    resolve_conflicts(package).map_err(|e| failed(summary, package, e))?;
+
+    match timing::install(package, || package.install()) {
         Ok(_) => {
             println!("Installed {} v{}", package.name, package.version);
+            summary.installed.push(package.name.clone());
         }
         Err(e) => {
             println!(
@@ -63,21 +395,386 @@ pub fn install(package: &Package, packages: &Vec<Package>) -> Result<(), Execute
                 package.name, package.version
             );
 
-            // Here we want to print the error for easier debugging.
-            // Should we only print this if a certain environment variable is set? (e.g DEBUG).
-            println!("{:?}", e);
+            report(&e);
+
+            return Err(failed(summary, package, ExecuteError::CompileFail));
+        }
+    }
+
+    Ok(())
+}
+
+// Builds the packages installing the targets has to build with up to `jobs` builds at once.
+// A package is built as soon as the packages it depends on are, and it's installed right after,
+// so the packages depending on it can be built against it. Installing happens on this thread,
+// one package at a time, as it may have to ask the user about conflicts.
+//
+// Whatever isn't built here, e.g. as its dependencies failed or form a cycle, is left to
+// [install_resolved], which reports it.
+fn build_concurrently(
+    targets: &[Package],
+    packages: &[Package],
+    jobs: usize,
+    summary: &mut InstallReport,
+) {
+    let order = match resolve_order(targets, packages) {
+        Ok(order) => order,
+        Err(_) => return,
+    };
+
+    let upgrade_deps = Config::load().flag("upgrade_deps");
+    let to_build = order
+        .iter()
+        .filter(|package| {
+            let kept = package.is_installed().is_some_and(|data| {
+                kept_in_order(package, &data.version, targets, &order, upgrade_deps)
+            });
+            let built = package
+                .is_built()
+                .is_some_and(|data| data.version == package.version);
+
+            !kept && !built
+        })
+        .collect::<Vec<&Package>>();
+
+    // every package to build, with the packages it's still waiting for.
+    let mut waiting = to_build
+        .iter()
+        .map(|package| {
+            let depends = package
+                .depends
+                .iter()
+                .filter(|depend| to_build.iter().any(|other| other.name == depend.name))
+                .map(|depend| depend.name.clone())
+                .collect::<HashSet<String>>();
+
+            (*package, depends)
+        })
+        .collect::<Vec<(&Package, HashSet<String>)>>();
+
+    let (queue, jobs_rx) = mpsc::channel::<Package>();
+    let (done, done_rx) = mpsc::channel::<(Package, Result<(), ParseError>)>();
+    let jobs_rx = Mutex::new(jobs_rx);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(to_build.len()) {
+            let done = done.clone();
+            let jobs_rx = &jobs_rx;
+
+            scope.spawn(move || loop {
+                // the queue is closed once nothing is left to build.
+                let package = match jobs_rx.lock().map(|rx| rx.recv()) {
+                    Ok(Ok(package)) => package,
+                    _ => break,
+                };
+                let built = timing::build(&package, || package.build());
+
+                if done.send((package, built)).is_err() {
+                    break;
+                }
+            });
+        }
+
+        drop(done);
+
+        let mut building = 0;
+        let ready = |waiting: &mut Vec<(&Package, HashSet<String>)>| {
+            let mut started = 0;
+
+            waiting.retain(|(package, depends)| {
+                if !depends.is_empty() {
+                    return true;
+                }
+
+                if queue.send((*package).clone()).is_ok() {
+                    started += 1;
+                }
+
+                false
+            });
+
+            started
+        };
+
+        building += ready(&mut waiting);
+
+        while building > 0 {
+            let (package, built) = match done_rx.recv() {
+                Ok(done) => done,
+                Err(_) => break,
+            };
+            building -= 1;
+
+            // the packages depending on a package that failed keep waiting for it.
+            if finish_build(&package, built, summary).is_err() || link(&package, summary).is_err() {
+                continue;
+            }
+
+            for (_, depends) in &mut waiting {
+                depends.remove(&package.name);
+            }
+
+            building += ready(&mut waiting);
+        }
+
+        // the workers stop once the queue is closed.
+        drop(queue);
+    });
+}
+
+// Whether the installed version of a package is kept, rather than installing its current version.
+//
+// Installing is idempotent, so a package that's installed at its current version is always kept.
+// Dependencies are kept at whatever version they're installed at as long as it satisfies their
+// constraint, see [Depend], which keeps installs from upgrading half the system.
+// --upgrade-deps (the `upgrade_deps` setting) upgrades them instead.
+fn keeps_installed(
+    package: &Package,
+    installed: &Version,
+    required: Option<&Depend>,
+    upgrade_deps: bool,
+) -> bool {
+    installed == &package.version
+        || (required.is_some_and(|depend| depend.is_satisfied_by(installed)) && !upgrade_deps)
+}
+
+// Whether the installed version of a package within the resolved order is kept. A dependency is
+// kept whenever it satisfies everything requiring it within the order, see [keeps_installed].
+fn kept_in_order(
+    package: &Package,
+    installed: &Version,
+    targets: &[Package],
+    order: &[Package],
+    upgrade_deps: bool,
+) -> bool {
+    match targets.iter().any(|target| target.name == package.name) {
+        true => keeps_installed(package, installed, None, upgrade_deps),
+        false => order
+            .iter()
+            .flat_map(|dependent| &dependent.depends)
+            .filter(|depend| depend.name == package.name)
+            .all(|depend| keeps_installed(package, installed, Some(depend), upgrade_deps)),
+    }
+}
+
+/// Prints what installing packages did, once everything is done.
+pub fn install_summary(summary: &InstallReport) {
+    let lines = [
+        ("built", &summary.built),
+        ("installed", &summary.installed),
+        ("already built", &summary.skipped),
+        ("unchanged", &summary.unchanged),
+    ];
+
+    for (label, names) in lines {
+        if !names.is_empty() {
+            println!("{}: {}", label, names.join(", "));
+        }
+    }
+
+    if !summary.failed.is_empty() {
+        let failed = summary
+            .failed
+            .iter()
+            .map(|(name, e)| format!("{} ({})", name, e))
+            .collect::<Vec<String>>();
+
+        println!("failed: {}", failed.join(", "));
+    }
+}
+
+/// Warns about every repository that hasn't been updated in longer than the `stale_days` setting,
+/// so packages aren't installed from outdated definitions by accident.
+///
+/// This can be turned off through --no-staleness-check (the `staleness_check` setting).
+pub fn check_staleness(repositories: &[Repo]) {
+    let config = Config::load();
+
+    if !config.flag("staleness_check") {
+        return;
+    }
+
+    let threshold = config.value("stale_days").parse::<u64>().unwrap_or(30);
+    let now = SystemTime::now();
+
+    for repository in repositories.iter().filter(|repo| repo.dir.is_dir()) {
+        match repository.days_since_update(now) {
+            Some(days) if days > threshold => warn(format!(
+                "{} last updated {} days ago; run pur update",
+                repository.name(),
+                days
+            )),
+            Some(_) => {}
+            None => warn(format!(
+                "{} has never been updated; run pur update",
+                repository.name()
+            )),
+        }
+    }
+}
+
+/// Rebuilds and reinstalls the packages and every one of their dependencies from source, in
+/// dependency order, whether they're installed already or not. This is useful after e.g. a toolchain change.
+pub fn rebuild_all(targets: &[Package], packages: &[Package]) -> Result<(), ExecuteError> {
+    for package in resolve_order(targets, packages)? {
+        rebuild(&package)?;
+    }
+
+    Ok(())
+}
+
+/// Rebuilds and relinks installed packages at the version they're at, e.g. after their links
+/// were clobbered. Unlike `pur update`, this doesn't look at versions at all. The dependencies
+/// are left alone, see [rebuild_all] for that.
+pub fn reinstall(to_reinstall: &[Package]) -> Result<(), ExecuteError> {
+    for package in to_reinstall {
+        rebuild(package)?;
+    }
+
+    Ok(())
+}
+
+// Builds the package again and links it, instead of the current build if it's installed.
+//
+// The build comes first, as its files only replace the current ones once it succeeded. A build
+// that fails leaves an installed package as it was, links and all.
+fn rebuild(package: &Package) -> Result<(), ExecuteError> {
+    if let Err(e) = package.build() {
+        println!(
+            "Failed to rebuild {} v{}... Skipping!",
+            package.name, package.version
+        );
+        report(&e);
+
+        return Err(ExecuteError::CompileFail);
+    }
+
+    // the links of the previous build have to go, as the new build may not have the same files.
+    if package.is_installed().is_some() {
+        if let Err(e) = package.remove_binaries() {
+            println!(
+                "Failed to remove the links of {} v{}",
+                package.name, package.version
+            );
+            report(&e);
+            return Err(ExecuteError::UninstallFail);
+        }
+    }
+
+    println!("Rebuilt {} v{}", package.name, package.version);
+
+    resolve_conflicts(package)?;
+
+    if let Err(e) = package.install() {
+        println!(
+            "Failed to install {} v{}... Skipping!",
+            package.name, package.version
+        );
+        report(&e);
+
+        return Err(ExecuteError::CompileFail);
+    }
+
+    println!("Installed {} v{}", package.name, package.version);
+
+    Ok(())
+}
 
+/// Reports every file in the way of installing the package at once, and gets them
+/// out of the way if --force or --backup (the `on_conflict` setting) is given.
+fn resolve_conflicts(package: &Package) -> Result<(), ExecuteError> {
+    let conflicts = match package.conflicts() {
+        Ok(conflicts) => conflicts,
+        Err(e) => {
+            println!(
+                "Couldn't check {} v{} for conflicting files",
+                package.name, package.version
+            );
+            report(&e);
             return Err(ExecuteError::CompileFail);
         }
+    };
+
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    println!(
+        "{} v{} conflicts with {} file(s):",
+        package.name,
+        package.version,
+        conflicts.len()
+    );
+
+    for conflict in &conflicts {
+        println!("  {}", conflict);
+    }
+
+    let backup = match Config::load().value("on_conflict") {
+        "force" => false,
+        "backup" => true,
+        _ => {
+            println!("Use --force to overwrite them, or --backup to keep a backup of them.");
+            return Err(ExecuteError::FileConflicts);
+        }
+    };
+
+    if let Err(e) = api::package::clear_conflicts(&conflicts, backup) {
+        println!("Failed to get the conflicting files out of the way, {}", e);
+        return Err(ExecuteError::FileConflicts);
     }
 
     Ok(())
 }
 
-pub fn update(repository: &Repo) -> Result<(), UpdateError> {
+/// Updates the repository, and queues every installed package of which a newer version
+/// has been pulled in, so they can be upgraded once every repository is up-to-date, see [upgrade].
+pub fn update(
+    repository: &Repo,
+    queue: &mut Vec<(Package, InstallData)>,
+) -> Result<(), UpdateError> {
     repository.update_repository(&mut |package, data| {
+        queue.push((package, data));
+
+        Ok(())
+    })?;
+
+    // pinned packages aren't upgraded past their pin, which the user should know about.
+    for package in repository.get_packages().unwrap_or_default() {
+        if let (Some(pin), Some(newer)) = (package.pin(), package.held_back()) {
+            warn(format!(
+                "{} is pinned to {} (v{}), v{} is available",
+                package.name, pin, package.version, newer
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Upgrades the packages queued by [update], or only prints them if `pretend` is set.
+///
+/// Upgrading can be turned off through the `upgrade_after_update` setting,
+/// in which case the packages are only reported as well.
+pub fn upgrade(queue: &[(Package, InstallData)], pretend: bool) {
+    if queue.is_empty() {
+        println!("All installed packages are up-to-date");
+        return;
+    }
+
+    let pretend = pretend || !Config::load().flag("upgrade_after_update");
+
+    for (package, data) in queue {
+        if pretend {
+            println!(
+                "Would upgrade {} from v{} to v{}",
+                package.name, data.version, package.version
+            );
+            continue;
+        }
+
         println!(
-            "Found new version {} for {}! Updating...Updating from {}...",
+            "Found new version {} for {}! Updating from {}...",
             package.version, package.name, data.version
         );
 
@@ -87,31 +784,34 @@ pub fn update(repository: &Repo) -> Result<(), UpdateError> {
                 println!("Updated {} to v{}", package.name, package.version);
             }
             Err(e) => {
-                println!(
-                    "Failed to update {} to v{}, because {:?}",
+                warn(format!(
+                    "failed to update {} to v{}, skipping it: {:?}",
                     package.name, package.version, e
-                );
-
-                println!("... Skipping!");
+                ));
             }
         };
-
-        Ok(())
-    })
+    }
 }
 
-pub fn remove(package: &Package) -> Result<(), ExecuteError> {
+/// Removes the package. Removing a package that isn't installed fails,
+/// unless `ignore_not_installed` is set (--ignore-not-installed).
+pub fn remove(package: &Package, ignore_not_installed: bool) -> Result<(), ExecuteError> {
     match package.uninstall() {
         Ok(_) => println!("Removed {} v{}", package.name, package.version),
+        Err(ParseError::NotInstalled) => {
+            println!("{} is not installed", package.name);
+
+            if !ignore_not_installed {
+                return Err(ExecuteError::UninstallFail);
+            }
+        }
         Err(e) => {
             println!(
                 "Failed to remove {} v{}... Skipping!",
                 package.name, package.version
             );
 
-            // Here we want to print the error for easier debugging.
-            // Should we only print this if a certain environment variable is set? (e.g DEBUG).
-            println!("{:?}", e);
+            report(&e);
 
             return Err(ExecuteError::UninstallFail);
         }
@@ -119,3 +819,1505 @@ pub fn remove(package: &Package) -> Result<(), ExecuteError> {
 
     Ok(())
 }
+
+/// Leaves out the packages to remove that aren't installed when `ignore_not_installed` is set,
+/// and fails listing all of them otherwise, before anything is removed.
+pub fn check_installed(
+    to_remove: Vec<Package>,
+    ignore_not_installed: bool,
+) -> Result<Vec<Package>, ExecuteError> {
+    check_installed_by(to_remove, ignore_not_installed, |package| {
+        package.is_installed().is_some()
+    })
+}
+
+fn check_installed_by(
+    to_remove: Vec<Package>,
+    ignore_not_installed: bool,
+    is_installed: impl Fn(&Package) -> bool,
+) -> Result<Vec<Package>, ExecuteError> {
+    let (installed, missing) = to_remove
+        .into_iter()
+        .partition::<Vec<Package>, _>(|package| is_installed(package));
+
+    for package in &missing {
+        println!("{} is not installed", package.name);
+    }
+
+    if !missing.is_empty() && !ignore_not_installed {
+        return Err(ExecuteError::NotInstalled(
+            missing.into_iter().map(|package| package.name).collect(),
+        ));
+    }
+
+    Ok(installed)
+}
+
+/// Fails whenever an installed package that isn't removed as well still depends on one of the
+/// packages to remove, as removing it would break that package. See [api::package::dependents].
+pub fn check_dependents(to_remove: &[Package], packages: &[Package]) -> Result<(), ExecuteError> {
+    for package in to_remove {
+        let dependents = api::package::dependents(&package.name, packages)
+            .into_iter()
+            .filter(|dependent| !to_remove.iter().any(|package| &package.name == dependent))
+            .collect::<Vec<String>>();
+
+        if !dependents.is_empty() {
+            println!(
+                "{} is still required by {}",
+                package.name,
+                dependents.join(", ")
+            );
+
+            return Err(ExecuteError::RequiredBy {
+                package: package.name.clone(),
+                dependents,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// What a recursive removal removes, see [with_orphans].
+#[derive(Debug, Default)]
+pub struct Removal {
+    /// The packages to remove, the requested ones first, followed by their orphaned dependencies.
+    pub packages: Vec<Package>,
+    /// How many of the packages were requested.
+    pub requested: usize,
+    /// The orphaned dependencies that are kept anyway, as they're held.
+    pub held: Vec<Package>,
+}
+
+/// Adds the installed dependencies which would be orphaned by removing the given packages,
+/// i.e. that no other installed package depends on anymore, to the packages to remove.
+///
+/// The dependencies of the orphans are checked as well, so whole trees of dependencies
+/// that were only pulled in for e.g. a meta-package get removed together with it.
+/// Held packages (see [Package::is_held]) are never removed as orphans, so they keep their
+/// own dependencies around too.
+pub fn with_orphans(to_remove: Vec<Package>, packages: &[Package]) -> Removal {
+    let installed = packages
+        .iter()
+        .filter(|package| package.is_installed().is_some())
+        .map(|package| package.name.clone())
+        .collect::<HashSet<String>>();
+    let held = packages
+        .iter()
+        .filter(|package| installed.contains(&package.name) && package.is_held())
+        .map(|package| package.name.clone())
+        .collect::<HashSet<String>>();
+
+    orphans(to_remove, packages, &installed, &held)
+}
+
+fn orphans(
+    to_remove: Vec<Package>,
+    packages: &[Package],
+    installed: &HashSet<String>,
+    held: &HashSet<String>,
+) -> Removal {
+    let mut removed = to_remove
+        .iter()
+        .map(|package| package.name.clone())
+        .collect::<HashSet<String>>();
+    let mut removal = Removal {
+        requested: to_remove.len(),
+        packages: to_remove,
+        held: Vec::new(),
+    };
+    let mut next = 0;
+
+    // every package that's going to be removed gets its dependencies checked once,
+    // a package that isn't installed doesn't hold on to anything.
+    while next < removal.packages.len() {
+        let package = &removal.packages[next];
+        let depends = match installed.contains(&package.name) {
+            true => package
+                .depends
+                .iter()
+                .map(|depend| depend.name.clone())
+                .collect(),
+            false => Vec::new(),
+        };
+        next += 1;
+
+        for depend in depends {
+            if removed.contains(&depend) || !installed.contains(&depend) {
+                continue;
+            }
+
+            let required = packages.iter().any(|package| {
+                installed.contains(&package.name)
+                    && !removed.contains(&package.name)
+                    && package.depends.iter().any(|other| other.name == depend)
+            });
+
+            if required {
+                continue;
+            }
+
+            // held packages are kept, so they still require their dependencies.
+            if held.contains(&depend) {
+                if !removal.held.iter().any(|package| package.name == depend) {
+                    removal.held.extend(
+                        packages
+                            .iter()
+                            .find(|package| package.name == depend)
+                            .cloned(),
+                    );
+                }
+
+                continue;
+            }
+
+            if let Some(package) = packages.iter().find(|package| package.name == depend) {
+                removed.insert(depend);
+                removal.packages.push(package.clone());
+            }
+        }
+    }
+
+    removal
+}
+
+/// Prints everything a recursive removal is going to remove, and which orphans are kept
+/// as they're held, so the user knows what they're confirming.
+pub fn removal_summary(removal: &Removal) {
+    println!("Removing {} package(s):", removal.packages.len());
+
+    for (i, package) in removal.packages.iter().enumerate() {
+        let reason = match i < removal.requested {
+            true => "",
+            false => " (orphaned)",
+        };
+
+        println!("  {} v{}{}", package.name, package.version, reason);
+    }
+
+    if !removal.held.is_empty() {
+        println!("Keeping {} held package(s):", removal.held.len());
+
+        for package in &removal.held {
+            println!("  {} v{}", package.name, package.version);
+        }
+    }
+}
+
+/// Reconstructs the manifest of an installed package, see [Package::reindex].
+pub fn reindex(package: &Package) -> Result<(), ExecuteError> {
+    match package.reindex() {
+        Ok(manifest) => println!(
+            "Recorded {} file(s) of {} v{}",
+            manifest.entries().len(),
+            package.name,
+            package.version
+        ),
+        Err(e) => {
+            println!(
+                "Failed to reindex {} v{}... Skipping!",
+                package.name, package.version
+            );
+
+            report(&e);
+
+            return Err(ExecuteError::InvalidPackage);
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints everything installing the packages would do without touching the system, the packages
+/// that would be built in order, and the links that would be created.
+///
+/// Dependencies are resolved like they are when installing, so missing dependencies,
+/// unsatisfied constraints and cycles fail the same way. The links of a package that isn't
+/// built yet are only known once its install script ran, so those can't be listed.
+pub fn install_dry_run(targets: &[Package], packages: &[Package]) -> Result<(), ExecuteError> {
+    let order = resolve_order(targets, packages)?;
+
+    for package in &order {
+        let step = planned_step(package, targets, &order);
+        println!("{}", describe_step(package, &step));
+
+        let built = match step {
+            Step::Keep(_) => continue,
+            Step::Upgrade { built, .. } => built,
+            Step::Install => true,
+            Step::BuildAndInstall => false,
+        };
+
+        if !built {
+            println!("  its links are only known once it's built");
+            continue;
+        }
+
+        let links = package
+            .links()
+            .and_then(|links| Ok((links, package.conflicts()?)));
+
+        match links {
+            Ok((links, conflicts)) => {
+                for (file, link) in links {
+                    println!("  link {} → {}", link.display(), file.display());
+                }
+
+                for conflict in conflicts {
+                    println!("  conflict: {}", conflict);
+                }
+            }
+            Err(e) => {
+                println!(
+                    "Couldn't figure out the links of {} v{}",
+                    package.name, package.version
+                );
+                report(&e);
+                return Err(ExecuteError::InvalidPackage);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints everything removing the package would delete, and the installed packages depending
+/// on it, without removing anything.
+pub fn remove_dry_run(package: &Package, packages: &[Package]) -> Result<(), ExecuteError> {
+    if package.is_built().is_none() {
+        println!("{} is not installed", package.name);
+        return Ok(());
+    }
+
+    let paths = match package.removal_paths() {
+        Ok(paths) => paths,
+        Err(e) => {
+            println!(
+                "Couldn't figure out the files of {} v{}",
+                package.name, package.version
+            );
+            report(&e);
+            return Err(ExecuteError::UninstallFail);
+        }
+    };
+
+    println!(
+        "Removing {} v{} would delete {} path(s):",
+        package.name,
+        package.version,
+        paths.len()
+    );
+
+    for path in paths {
+        println!("  {}", path.display());
+    }
+
+    for dependent in packages.iter().filter(|dependent| {
+        dependent
+            .depends
+            .iter()
+            .any(|depend| depend.name == package.name)
+            && dependent.is_installed().is_some()
+    }) {
+        println!(
+            "Warning: {} v{} depends on {}",
+            dependent.name, dependent.version, package.name
+        );
+    }
+
+    Ok(())
+}
+
+// The global flags that set a setting to a fixed value, as (flag, key, value).
+const FLAG_SETTINGS: &[(&str, &str, &str)] = &[
+    ("verbose-scripts", "verbose_scripts", "yes"),
+    ("non-interactive", "noninteractive", "yes"),
+    ("yes", "assume_yes", "yes"),
+    ("verbose", "verbose", "yes"),
+    ("no-staleness-check", "staleness_check", "no"),
+    ("audit-scripts", "audit_scripts", "yes"),
+    ("color-diagnostics", "color_diagnostics", "yes"),
+    ("fallback-binary", "fallback_binary", "yes"),
+    ("time", "time", "yes"),
+    ("no-ldconfig", "ldconfig", "no"),
+];
+
+/// The settings given on the command line through the global flags, as (key, value) pairs.
+pub fn cli_settings(matches: &ArgMatches) -> Vec<(&'static str, String)> {
+    let mut settings = Vec::new();
+
+    // The root is resolved right away, as scripts are given it while running from other directories.
+    if let Some(root) = matches.get_one::<String>("root") {
+        let root = std::path::absolute(root).unwrap_or_else(|_| root.into());
+        settings.push(("root", root.display().to_string()));
+    }
+
+    if let Some(profile) = matches.get_one::<String>("profile") {
+        settings.push(("profile", profile.to_owned()));
+    }
+
+    if let Some(rate) = matches.get_one::<u64>("max-rate") {
+        settings.push(("max_rate", rate.to_string()));
+    }
+
+    for (flag, key, value) in FLAG_SETTINGS {
+        if matches.is_present(flag) {
+            settings.push((*key, value.to_string()));
+        }
+    }
+
+    // --enable adds to the features of the config file, rather than replacing them.
+    if let Some(enabled) = matches.get_many::<String>("enable") {
+        settings.push(("features", with_features(enabled)));
+    }
+
+    settings
+}
+
+pub fn config(matches: &ArgMatches) {
+    let mut config = Config::load();
+
+    for (key, value) in cli_settings(matches) {
+        config.set(key, &value, Source::Cli);
+    }
+
+    let path = config_path();
+
+    if path.exists() {
+        println!("# using config file {}", path.display());
+    } else {
+        println!("# no config file found at {}", path.display());
+    }
+
+    for setting in config.settings() {
+        println!("{} = {} ({})", setting.key, setting.value, setting.source);
+    }
+}
+
+// The `features` setting with the given features added to it, see [api::config::enabled_features].
+fn with_features<'a>(enabled: impl Iterator<Item = &'a String>) -> String {
+    let config = Config::load();
+    let mut features = config
+        .value("features")
+        .split(',')
+        .map(str::trim)
+        .filter(|feature| !feature.is_empty())
+        .map(String::from)
+        .collect::<Vec<String>>();
+
+    for feature in enabled {
+        if !features.contains(feature) {
+            features.push(feature.clone());
+        }
+    }
+
+    features.join(",")
+}
+
+/// Prints every warning emitted while running the command once more, so they aren't lost within
+/// the output of e.g. builds, see [api::warnings::warn].
+pub fn report_warnings() {
+    let warnings = api::warnings::take();
+
+    if warnings.is_empty() {
+        return;
+    }
+
+    println!();
+    println!("Warnings ({}):", warnings.len());
+
+    for warning in warnings {
+        println!("  {}", warning);
+    }
+}
+
+/// Prints the versions of pur, its library and the install database, together with the
+/// config file and repositories in use, so they can be pasted into a bug report at once.
+pub fn version(repositories: &[Repo]) {
+    let path = config_path();
+    let found = |exists: bool| match exists {
+        true => "",
+        false => " (not found)",
+    };
+
+    println!("pur {}", env!("CARGO_PKG_VERSION"));
+    println!("api {}", api::VERSION);
+    println!(
+        "db-version {}",
+        api::package::db_version().unwrap_or_else(|| "unversioned".to_owned())
+    );
+    println!("config {}{}", path.display(), found(path.exists()));
+    println!("repositories:");
+
+    for repo in repositories {
+        match &repo.url {
+            Some(url) => println!(
+                "  {} at {}{}",
+                url,
+                repo.dir.display(),
+                found(repo.dir.is_dir())
+            ),
+            None => println!("  {}{}", repo.dir.display(), found(repo.dir.is_dir())),
+        }
+    }
+}
+
+/// Prints what's known about a package, including how it was built,
+/// followed by the tree of its dependencies.
+pub fn info(package: &Package, packages: &[Package]) {
+    let status = match (package.is_installed(), package.is_built()) {
+        (Some(_), _) => "installed",
+        (None, Some(_)) => "built",
+        (None, None) => "not installed",
+    };
+
+    println!("name: {}", package.name);
+    println!("version: {}", package.version);
+    println!(
+        "depends: {}",
+        package
+            .depends
+            .iter()
+            .map(|depend| depend.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+
+    for depend in &package.conditional_depends {
+        println!("depends with {}: {}", depend.feature, depend.depend);
+    }
+
+    if !package.aliases.is_empty() {
+        println!("aliases: {}", package.aliases.join(", "));
+    }
+
+    println!("directory: {}", package.dir().display());
+    println!("status: {}", status);
+
+    if let Some(info) = package.build_info() {
+        let mut info = info.into_iter().collect::<Vec<(String, String)>>();
+        info.sort();
+
+        for (key, value) in info {
+            println!("{}: {}", key, value);
+        }
+    }
+
+    println!("dependency tree:");
+
+    for line in dependency_tree(package, packages) {
+        println!("  {}", line);
+    }
+}
+
+/// Prints the installed packages depending on the package, see [api::package::dependents].
+pub fn rdeps(package: &Package, packages: &[Package]) {
+    let dependents = api::package::dependents(&package.name, packages);
+
+    match dependents.is_empty() {
+        true => println!("required by: nothing"),
+        false => println!("required by: {}", dependents.join(", ")),
+    }
+}
+
+/// Renders the dependencies of a package recursively, one per line and indented by their depth,
+/// marked as `[installed]`, `[built]` or `[missing]` whenever the repositories don't have a
+/// version satisfying it. A dependency on a package that's still being expanded is marked as
+/// `[cycle]` instead of being expanded again.
+fn dependency_tree(package: &Package, packages: &[Package]) -> Vec<String> {
+    let mut lines = vec![format!("{} v{}", package.name, package.version)];
+
+    expand_depends(
+        package,
+        packages,
+        &mut vec![package.name.clone()],
+        &mut lines,
+    );
+    lines
+}
+
+fn expand_depends(
+    package: &Package,
+    packages: &[Package],
+    expanding: &mut Vec<String>,
+    lines: &mut Vec<String>,
+) {
+    let indent = "  ".repeat(expanding.len());
+
+    for depend in &package.depends {
+        let found = packages
+            .iter()
+            .find(|package| package.name == depend.name)
+            .filter(|package| depend.is_satisfied_by(&package.version));
+
+        let found = match found {
+            Some(found) => found,
+            None => {
+                lines.push(format!("{}{} [missing]", indent, depend));
+                continue;
+            }
+        };
+
+        let label = format!("{}{} v{}", indent, found.name, found.version);
+
+        if expanding.contains(&found.name) {
+            lines.push(format!("{} [cycle]", label));
+            continue;
+        }
+
+        lines.push(match (found.is_installed(), found.is_built()) {
+            (Some(_), _) => format!("{} [installed]", label),
+            (None, Some(_)) => format!("{} [built]", label),
+            (None, None) => label,
+        });
+
+        expanding.push(found.name.clone());
+        expand_depends(found, packages, expanding, lines);
+        expanding.pop();
+    }
+}
+
+/// Prints every problem found within the package definition,
+/// and returns whether the package is free of problems.
+pub fn lint(package: &Package, packages: &[Package]) -> bool {
+    let warnings = package.validate(packages);
+
+    if warnings.is_empty() {
+        println!("{} v{}: no problems found", package.name, package.version);
+        return true;
+    }
+
+    println!(
+        "{} v{}: {} problem(s) found",
+        package.name,
+        package.version,
+        warnings.len()
+    );
+
+    for warning in warnings {
+        println!("  {}", warning);
+    }
+
+    false
+}
+
+/// Resolves the order the given packages and all of their dependencies have to be installed in,
+/// where every dependency comes before the packages depending on it, and every package
+/// appears only once.
+///
+/// The order only depends on the order of the given packages and their `depends` files,
+/// so it's the same for a given repository state.
+pub fn resolve_order(
+    targets: &[Package],
+    packages: &[Package],
+) -> Result<Vec<Package>, ExecuteError> {
+    resolve(targets, packages).map_err(|(e, _)| e)
+}
+
+// Resolves the order like [resolve_order], failing together with the packages that led to the
+// package it failed at, outermost first, so those can fail along with it.
+fn resolve(
+    targets: &[Package],
+    packages: &[Package],
+) -> Result<Vec<Package>, (ExecuteError, Vec<String>)> {
+    // The graph is walked with an explicit stack rather than recursively,
+    // so arbitrarily long dependency chains can't overflow the stack.
+    let mut by_name = HashMap::<&str, &Package>::new();
+
+    for package in packages {
+        // the first package with a name takes precedence, like everywhere else.
+        by_name.entry(package.name.as_str()).or_insert(package);
+    }
+
+    let chain = |stack: &[(&Package, usize)]| {
+        stack
+            .iter()
+            .map(|(package, _)| package.name.clone())
+            .collect::<Vec<String>>()
+    };
+
+    let mut seen = HashSet::<String>::new();
+    let mut order = Vec::<Package>::new();
+
+    for target in targets {
+        if !seen.insert(target.name.clone()) {
+            continue;
+        }
+
+        let mut trace = Trace::new("resolving", target);
+        // every package being visited, with the index of the next dependency to visit.
+        let mut stack = vec![(target, 0)];
+
+        while let Some((package, next)) = stack.last_mut() {
+            let package = *package;
+
+            let ele = match package.depends.get(*next) {
+                Some(ele) => ele,
+                // all of its dependencies come before it, so it's ready.
+                None => {
+                    order.push(package.clone());
+                    stack.pop();
+
+                    // the "requires" and "found" steps that led to this package.
+                    if !stack.is_empty() {
+                        trace.pop();
+                        trace.pop();
+                    }
+
+                    continue;
+                }
+            };
+
+            *next += 1;
+            trace.push(format!("requires {}", ele));
+
+            match by_name.get(ele.name.as_str()) {
+                Some(depend) if !ele.is_satisfied_by(&depend.version) => {
+                    trace.fail(&format!("found {}, doesn't satisfy it", depend.version));
+                    return Err((ExecuteError::NoDependFound, chain(&stack)));
+                }
+                Some(depend) => {
+                    trace.found(depend);
+
+                    // the package is still waiting for its dependencies to be ordered.
+                    if let Some(start) = stack
+                        .iter()
+                        .position(|(visiting, _)| visiting.name == depend.name)
+                    {
+                        let cycle = stack[start..]
+                            .iter()
+                            .map(|(package, _)| package.name.clone())
+                            .chain([depend.name.clone()])
+                            .collect::<Vec<String>>();
+
+                        trace.fail("DEPENDENCY CYCLE");
+                        return Err((ExecuteError::DependencyCycle(cycle), chain(&stack)));
+                    }
+
+                    if seen.insert(depend.name.clone()) {
+                        stack.push((depend, 0));
+                    } else {
+                        trace.pop();
+                        trace.pop();
+                    }
+                }
+                None => {
+                    trace.fail("NOT FOUND");
+                    return Err((ExecuteError::NoDependFound, chain(&stack)));
+                }
+            }
+        }
+    }
+
+    Ok(order)
+}
+
+/// Prints the packages that would be built and installed for the given targets, in order,
+/// without building or installing anything.
+pub fn plan(targets: &[Package], packages: &[Package], json: bool) -> Result<(), ExecuteError> {
+    let order = resolve_order(targets, packages)?;
+
+    if json {
+        let entries = order
+            .iter()
+            .map(|package| {
+                let step = planned_step(package, targets, &order);
+
+                json!({
+                    "name": package.name,
+                    "version": package.version.to_string(),
+                    "satisfied": matches!(step, Step::Keep(_)),
+                    "action": match step {
+                        Step::Keep(_) => "keep",
+                        Step::Upgrade { .. } => "upgrade",
+                        Step::Install => "install",
+                        Step::BuildAndInstall => "build",
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        println!("{}", json!({ "packages": entries }));
+        return Ok(());
+    }
+
+    for package in &order {
+        println!(
+            "{}",
+            describe_step(package, &planned_step(package, targets, &order))
+        );
+    }
+
+    Ok(())
+}
+
+// What installing does with a package of the resolved order, see [install].
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    /// It's kept at the version it's installed at.
+    Keep(Version),
+    /// It replaces the version it's installed at, and is built already or not.
+    Upgrade {
+        from: Version,
+        built: bool,
+    },
+    /// It's built already, so it only has to be linked.
+    Install,
+    BuildAndInstall,
+}
+
+fn planned_step(package: &Package, targets: &[Package], order: &[Package]) -> Step {
+    step(
+        package,
+        package.is_installed().map(|data| data.version),
+        package
+            .is_built()
+            .is_some_and(|data| data.version == package.version),
+        targets,
+        order,
+        Config::load().flag("upgrade_deps"),
+    )
+}
+
+// Decides the step like installing does, so --plan and --dry-run always agree with it.
+fn step(
+    package: &Package,
+    installed: Option<Version>,
+    built: bool,
+    targets: &[Package],
+    order: &[Package],
+    upgrade_deps: bool,
+) -> Step {
+    match installed {
+        Some(version) if kept_in_order(package, &version, targets, order, upgrade_deps) => {
+            Step::Keep(version)
+        }
+        Some(from) => Step::Upgrade { from, built },
+        None if built => Step::Install,
+        None => Step::BuildAndInstall,
+    }
+}
+
+fn describe_step(package: &Package, step: &Step) -> String {
+    match step {
+        Step::Keep(version) => format!("{} v{}: already installed", package.name, version),
+        Step::Upgrade { from, .. } => format!(
+            "{} v{}: upgrade from v{}",
+            package.name, package.version, from
+        ),
+        Step::Install => format!("{} v{}: install", package.name, package.version),
+        Step::BuildAndInstall => {
+            format!("{} v{}: build & install", package.name, package.version)
+        }
+    }
+}
+
+/// Prints every installed package of which a newer version is available, without upgrading anything.
+///
+/// The available version is the one of the first repository containing the package,
+/// which is the one that would be installed, see --repo-precedence.
+pub fn outdated(packages: &[Package], json: bool) {
+    let mut seen = HashSet::<&str>::new();
+    let mut outdated = Vec::<(&Package, InstallData)>::new();
+
+    for package in packages {
+        if !seen.insert(&package.name) {
+            continue;
+        }
+
+        let data = match package.is_installed() {
+            Some(data) => data,
+            None => continue,
+        };
+
+        // we can't tell whether a package without a version is newer or not.
+        if package.version.is_empty() || data.version.is_empty() {
+            continue;
+        }
+
+        if api::repo::is_update(&package.version, &data.version) {
+            outdated.push((package, data));
+        }
+    }
+
+    if json {
+        let entries = outdated
+            .iter()
+            .map(|(package, data)| {
+                json!({
+                    "name": package.name,
+                    "installed": data.version.to_string(),
+                    "available": package.version.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        println!("{}", json!({ "packages": entries }));
+        return;
+    }
+
+    let width = outdated
+        .iter()
+        .map(|(package, _)| package.name.len())
+        .max()
+        .unwrap_or_default();
+
+    for (package, data) in outdated {
+        println!(
+            "{:width$}  {} → {}",
+            package.name,
+            data.version,
+            package.version,
+            width = width
+        );
+    }
+}
+
+/// Prints every package within the install database with its version, sorted by name.
+///
+/// Unlike `search --installed` this doesn't need the repositories, so packages that were removed
+/// from them are listed too. With `built`, the packages that are built but not installed are
+/// listed as well.
+pub fn list(built: bool, json: bool) {
+    let packages = api::package::installed_packages(built);
+    let installed = api::package::installed_packages(false)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<HashSet<String>>();
+
+    if json {
+        let entries = packages
+            .iter()
+            .map(|(name, data)| {
+                json!({
+                    "name": name,
+                    "version": data.version.to_string(),
+                    "installed": installed.contains(name),
+                    "installed_at": data.installed_at.map(unix_secs),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        println!("{}", json!({ "packages": entries }));
+        return;
+    }
+
+    let width = |column: fn(&(String, InstallData)) -> usize| {
+        packages.iter().map(column).max().unwrap_or_default()
+    };
+    let name_width = width(|(name, _)| name.len());
+    let version_width = width(|(_, data)| data.version.to_string().len());
+
+    for (name, data) in &packages {
+        // packages installed before the time was recorded don't have one.
+        let status = match (installed.contains(name), data.installed_at) {
+            (true, Some(time)) => format_time(time),
+            (true, None) => "-".to_owned(),
+            (false, _) => "(built)".to_owned(),
+        };
+
+        println!(
+            "{:name_width$}  {:version_width$}  {}",
+            name,
+            data.version.to_string(),
+            status,
+            name_width = name_width,
+            version_width = version_width
+        );
+    }
+}
+
+/// Formats a point in time as a UTC date and time, e.g. `2024-03-01 14:05`.
+pub fn format_time(time: SystemTime) -> String {
+    let secs = unix_secs(time);
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // converts the days since the epoch into a date of the proleptic gregorian calendar.
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60
+    )
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Prints every version of a package the repositories contain, in the order of the repositories.
+///
+/// The first one is the one that's selected for installation, see --repo-precedence.
+pub fn versions(name: &str, packages: &[Package]) {
+    let candidates = packages
+        .iter()
+        .filter(|package| package.name == name)
+        .collect::<Vec<&Package>>();
+
+    if candidates.is_empty() {
+        warn(format!("package {} does not exist", name));
+        return;
+    }
+
+    // the install database only knows packages by name, so this is the same for every candidate.
+    let installed = candidates[0].is_installed();
+
+    for (i, package) in candidates.iter().enumerate() {
+        let repo = package
+            .dir()
+            .parent()
+            .map(|repo| repo.display().to_string())
+            .unwrap_or_default();
+
+        let mut line = format!("{} v{} in {}", package.name, package.version, repo);
+
+        if i == 0 {
+            line += " [selected]";
+        }
+
+        if installed
+            .as_ref()
+            .is_some_and(|data| data.version == package.version)
+        {
+            line += " [installed]";
+        }
+
+        println!("{}", line);
+    }
+}
+
+/// Prints the `checksums` file of the package within `dir`, see [api::package::source_checksums].
+pub fn checksum(dir: &Path) -> Result<(), ExecuteError> {
+    // the package has to be parsed, as its sources may refer to its name and version.
+    let checksums = Package::try_from(dir.to_path_buf())
+        .and_then(|package| api::package::source_checksums(&package));
+
+    match checksums {
+        Ok(lines) if lines.is_empty() => {
+            warn(format!("{} doesn't have any sources", dir.display()));
+        }
+        Ok(lines) => {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        Err(e) => {
+            println!("Failed to compute the checksums of {}", dir.display());
+            report(&e);
+            return Err(ExecuteError::ChecksumFail);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn clean(matches: &ArgMatches) -> Result<(), ExecuteError> {
+    if matches.is_present("logs") {
+        match api::package::purge_build_logs() {
+            Ok(deleted) => println!("Deleted {} build log(s)", deleted),
+            Err(e) => {
+                println!("Failed to delete build logs");
+                report(&e);
+                return Err(ExecuteError::CleanFail);
+            }
+        }
+    }
+
+    if matches.is_present("build") {
+        match api::package::purge_build_dirs() {
+            Ok(deleted) => println!("Deleted {} build directories", deleted),
+            Err(e) => {
+                println!("Failed to delete build directories");
+                report(&e);
+                return Err(ExecuteError::CleanFail);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the install database for problems, and fixes them if `fix` is set.
+pub fn doctor(fix: bool) -> Result<(), ExecuteError> {
+    let mut problems = 0;
+
+    for name in api::package::interrupted_removals() {
+        problems += 1;
+
+        if !fix {
+            println!("The removal of {} was interrupted", name);
+            continue;
+        }
+
+        match api::package::finish_removal(&name) {
+            Ok(_) => println!("Finished the interrupted removal of {}", name),
+            Err(e) => {
+                println!("Failed to finish the interrupted removal of {}", name);
+                report(&e);
+                return Err(ExecuteError::UninstallFail);
+            }
+        }
+    }
+
+    match (problems, fix) {
+        (0, _) => println!("No problems found"),
+        (_, false) => println!(
+            "Found {} problem(s), run pur doctor --fix to fix them",
+            problems
+        ),
+        (_, true) => println!("Fixed {} problem(s)", problems),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn package(dir: &Path, name: &str, depends: &str) -> Package {
+        let dir = dir.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("version"), "1.0").unwrap();
+        fs::write(dir.join("depends"), depends).unwrap();
+
+        Package::try_from(dir).unwrap()
+    }
+
+    #[test]
+    fn formats_times_as_dates() {
+        let time = |secs: u64| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+
+        assert_eq!(format_time(time(0)), "1970-01-01 00:00");
+        assert_eq!(format_time(time(951_782_400)), "2000-02-29 00:00");
+        assert_eq!(format_time(time(1_709_301_900)), "2024-03-01 14:05");
+        assert_eq!(format_time(time(1_735_689_599)), "2024-12-31 23:59");
+    }
+
+    #[test]
+    fn finds_packages_by_alias() {
+        let dir = tempfile::tempdir().unwrap();
+        let nodejs = package(dir.path(), "nodejs", "");
+        fs::write(
+            nodejs.dir().join("aliases"),
+            "node\nnode-lts # the LTS release\n",
+        )
+        .unwrap();
+
+        let packages = [
+            Package::try_from(nodejs.dir().to_path_buf()).unwrap(),
+            package(dir.path(), "node", ""),
+            package(dir.path(), "npm", "nodejs"),
+        ];
+
+        let found = |name: &str| find_named(name, &packages).map(|package| package.name);
+
+        // a package of that name always wins over an alias.
+        assert_eq!(found("node").as_deref(), Some("node"));
+        assert_eq!(found("node-lts").as_deref(), Some("nodejs"));
+        assert_eq!(found("npm").as_deref(), Some("npm"));
+        assert_eq!(found("deno"), None);
+    }
+
+    #[test]
+    fn checks_packages_are_installed() {
+        let dir = tempfile::tempdir().unwrap();
+        let to_remove = || {
+            vec![
+                package(dir.path(), "curl", ""),
+                package(dir.path(), "git", ""),
+                package(dir.path(), "zlib", ""),
+            ]
+        };
+        let is_installed = |package: &Package| package.name != "git";
+
+        match check_installed_by(to_remove(), false, is_installed) {
+            Err(ExecuteError::NotInstalled(missing)) => assert_eq!(missing, ["git"]),
+            other => panic!(
+                "expected git not to be installed, got {:?}",
+                other.map(|_| ())
+            ),
+        }
+
+        let kept = check_installed_by(to_remove(), true, is_installed).unwrap();
+        assert_eq!(
+            kept.iter()
+                .map(|package| package.name.as_str())
+                .collect::<Vec<&str>>(),
+            ["curl", "zlib"]
+        );
+    }
+
+    #[test]
+    fn reports_every_missing_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = [
+            package(dir.path(), "curl", ""),
+            package(dir.path(), "zlib", ""),
+        ];
+        let names = ["curl", "crul", "zlib", "zilb"].map(String::from);
+
+        let found = find_all(names[..1].iter(), &packages, find_named).unwrap();
+        assert_eq!(found[0].name, "curl");
+
+        match find_all(names.iter(), &packages, find_named) {
+            Err(ExecuteError::PackageNotFound(missing)) => assert_eq!(missing, ["crul", "zilb"]),
+            other => panic!("expected missing packages, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn reports_failed_installs() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = vec![
+            package(dir.path(), "app", "lib"),
+            package(dir.path(), "lib", "missing"),
+            package(dir.path(), "tool", "lib"),
+        ];
+
+        let summary = install(&[packages[0].clone(), packages[2].clone()], &packages);
+        let failed = summary
+            .failed
+            .iter()
+            .map(|(name, e)| (name.as_str(), e.to_string()))
+            .collect::<Vec<(&str, String)>>();
+
+        // the app fails because of its library, which doesn't stop the tool from being tried,
+        // but the library isn't tried again for it.
+        assert_eq!(
+            failed,
+            [
+                ("lib", "NoDependFound".to_owned()),
+                ("app", "NoDependFound".to_owned()),
+                ("tool", "NoDependFound".to_owned()),
+            ]
+        );
+        assert!(summary.built.is_empty() && summary.installed.is_empty());
+        assert!(!summary.changed());
+    }
+
+    #[test]
+    fn keeps_installed_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let openssl = package(dir.path(), "openssl", "");
+        let older = "0.9".parse::<Version>().unwrap();
+        let any = "openssl".parse::<Depend>().unwrap();
+        let recent = "openssl>=1.0".parse::<Depend>().unwrap();
+
+        // a dependency that's installed at all isn't upgraded...
+        assert!(keeps_installed(&openssl, &older, Some(&any), false));
+        assert!(keeps_installed(
+            &openssl,
+            &openssl.version,
+            Some(&any),
+            false
+        ));
+        // ...unless the dependencies are upgraded too...
+        assert!(!keeps_installed(&openssl, &older, Some(&any), true));
+        assert!(keeps_installed(
+            &openssl,
+            &openssl.version,
+            Some(&any),
+            true
+        ));
+        // ...or its version doesn't satisfy the constraint.
+        assert!(!keeps_installed(&openssl, &older, Some(&recent), false));
+        assert!(keeps_installed(
+            &openssl,
+            &openssl.version,
+            Some(&recent),
+            false
+        ));
+
+        // the packages that are installed explicitly are always brought to their current version.
+        assert!(!keeps_installed(&openssl, &older, None, false));
+        assert!(keeps_installed(&openssl, &openssl.version, None, false));
+    }
+
+    #[test]
+    fn resolves_diamond_dependencies_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = [
+            package(dir.path(), "a", "b\nc"),
+            package(dir.path(), "b", "d"),
+            package(dir.path(), "c", "d"),
+            package(dir.path(), "d", ""),
+        ];
+
+        let order = resolve_order(&packages[..1], &packages)
+            .unwrap()
+            .into_iter()
+            .map(|package| package.name)
+            .collect::<Vec<String>>();
+
+        assert_eq!(order, ["d", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn renders_dependency_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = vec![
+            package(dir.path(), "a", "b\nd>=2.0"),
+            package(dir.path(), "b", "c\nmissing"),
+            package(dir.path(), "c", "b"),
+            package(dir.path(), "d", ""),
+        ];
+
+        assert_eq!(
+            dependency_tree(&packages[0], &packages),
+            [
+                "a v1.0",
+                "  b v1.0",
+                "    c v1.0",
+                "      b v1.0 [cycle]",
+                "    missing [missing]",
+                "  d>=2.0 [missing]",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_dependency_cycles() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = vec![
+            package(dir.path(), "a", "b"),
+            package(dir.path(), "b", "c"),
+            package(dir.path(), "c", "a"),
+        ];
+        let cycle = |result: Result<(), ExecuteError>| match result {
+            Err(ExecuteError::DependencyCycle(cycle)) => cycle,
+            _ => panic!("the cycle wasn't detected"),
+        };
+
+        // the cycle is caught before anything is built.
+        assert_eq!(cycle(build(&packages[0], &packages)), ["a", "b", "c", "a"]);
+        assert_eq!(
+            cycle(resolve_order(&packages[1..2], &packages).map(|_| ())),
+            ["b", "c", "a", "b"]
+        );
+
+        let summary = install(&packages[..1], &packages);
+        let failed = summary
+            .failed
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<&str>>();
+
+        assert_eq!(failed, ["c", "b", "a"]);
+        assert!(summary.built.is_empty());
+    }
+
+    #[test]
+    fn rejects_unsatisfied_constraints() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = [
+            package(dir.path(), "curl", "openssl>=3.0\nzlib"),
+            package(dir.path(), "wget", "openssl <= 1.0"),
+            package(dir.path(), "openssl", ""),
+            package(dir.path(), "zlib", ""),
+        ];
+
+        // every package is at version 1.0.
+        assert!(matches!(
+            resolve_order(&packages[..1], &packages),
+            Err(ExecuteError::NoDependFound)
+        ));
+
+        let order = resolve_order(&packages[1..2], &packages)
+            .unwrap()
+            .into_iter()
+            .map(|package| package.name)
+            .collect::<Vec<String>>();
+
+        assert_eq!(order, ["openssl", "wget"]);
+    }
+
+    #[test]
+    fn plans_upgrades_of_outdated_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = [
+            package(dir.path(), "app", "lib>=1.0"),
+            package(dir.path(), "lib", ""),
+        ];
+        let order = resolve_order(&packages[..1], &packages).unwrap();
+        let v = |version: &str| version.parse::<Version>().unwrap();
+        let step = |package: &Package, installed: &str, built: bool| {
+            step(
+                package,
+                Some(v(installed)),
+                built,
+                &packages[..1],
+                &order,
+                false,
+            )
+        };
+
+        // an outdated target is upgraded, like installing does...
+        assert_eq!(
+            step(&packages[0], "0.9", false),
+            Step::Upgrade {
+                from: v("0.9"),
+                built: false
+            }
+        );
+        assert_eq!(step(&packages[0], "1.0", true), Step::Keep(v("1.0")));
+        // ...while a dependency is kept as long as it satisfies what requires it.
+        assert_eq!(
+            step(&packages[1], "0.9", true),
+            Step::Upgrade {
+                from: v("0.9"),
+                built: true
+            }
+        );
+        assert_eq!(step(&packages[1], "1.0.0", false), Step::Keep(v("1.0.0")));
+        assert_eq!(
+            describe_step(&packages[0], &step(&packages[0], "0.9", false)),
+            "app v1.0: upgrade from v0.9"
+        );
+    }
+
+    #[test]
+    fn removes_orphaned_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = [
+            package(dir.path(), "desktop", "wm\nterminal\nfonts"),
+            package(dir.path(), "wm", "xlib"),
+            package(dir.path(), "terminal", "fonts"),
+            package(dir.path(), "xlib", ""),
+            package(dir.path(), "fonts", ""),
+            package(dir.path(), "editor", "fonts"),
+        ];
+
+        let set = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<HashSet<String>>()
+        };
+        let names = |packages: Vec<Package>| {
+            packages
+                .into_iter()
+                .map(|package| package.name)
+                .collect::<Vec<String>>()
+        };
+        let nothing_held = HashSet::new();
+
+        // fonts is still required by the editor, the terminal isn't installed.
+        let removed = orphans(
+            vec![packages[0].clone()],
+            &packages,
+            &set(&["desktop", "wm", "xlib", "fonts", "editor"]),
+            &nothing_held,
+        );
+        assert_eq!(removed.requested, 1);
+        assert_eq!(names(removed.packages), ["desktop", "wm", "xlib"]);
+
+        let removed = orphans(
+            vec![packages[0].clone()],
+            &packages,
+            &set(&["desktop", "wm", "terminal", "xlib", "fonts"]),
+            &nothing_held,
+        );
+        // fonts is only orphaned once the terminal goes, which is when it's checked again.
+        assert_eq!(
+            names(removed.packages),
+            ["desktop", "wm", "terminal", "fonts", "xlib"]
+        );
+
+        // the held wm stays, and so does the xlib it depends on.
+        let removed = orphans(
+            vec![packages[0].clone()],
+            &packages,
+            &set(&["desktop", "wm", "terminal", "xlib", "fonts"]),
+            &set(&["wm"]),
+        );
+        assert_eq!(names(removed.packages), ["desktop", "terminal", "fonts"]);
+        assert_eq!(names(removed.held), ["wm"]);
+    }
+
+    #[test]
+    fn resolves_long_dependency_chains() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("template");
+        fs::create_dir_all(&template).unwrap();
+        fs::write(template.join("version"), "1.0").unwrap();
+        fs::write(template.join("depends"), "").unwrap();
+        let template = Package::try_from(template).unwrap();
+
+        // pkg0 requires pkg1, which requires pkg2, ... down to pkg4999.
+        let depth = 5000;
+        let packages = (0..depth)
+            .map(|n| {
+                let mut package = template.clone();
+                package.name = format!("pkg{}", n);
+                package.depends = match n + 1 < depth {
+                    true => vec![format!("pkg{}", n + 1).parse().unwrap()],
+                    false => Vec::new(),
+                };
+                package
+            })
+            .collect::<Vec<Package>>();
+
+        let order = resolve_order(&packages[..1], &packages).unwrap();
+
+        assert_eq!(order.len(), depth);
+        assert!(order
+            .iter()
+            .rev()
+            .map(|package| &package.name)
+            .eq(packages.iter().map(|package| &package.name)));
+    }
+
+    #[test]
+    fn installs_long_dependency_chains() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = package(dir.path(), "template", "");
+
+        // pkg0 requires pkg1, ... down to pkg4999, which requires a package that doesn't exist.
+        let depth = 5000;
+        let packages = (0..depth)
+            .map(|n| {
+                let mut package = template.clone();
+                package.name = format!("pkg{}", n);
+                package.depends = vec![match n + 1 < depth {
+                    true => format!("pkg{}", n + 1).parse().unwrap(),
+                    false => "missing".parse().unwrap(),
+                }];
+                package
+            })
+            .collect::<Vec<Package>>();
+
+        let summary = install(&packages[..1], &packages);
+
+        // every package fails with the one that's missing a dependency, innermost first.
+        assert_eq!(summary.failed.len(), depth);
+        assert!(summary
+            .failed
+            .iter()
+            .rev()
+            .map(|(name, _)| name)
+            .eq(packages.iter().map(|package| &package.name)));
+
+        assert!(matches!(
+            build(&packages[0], &packages),
+            Err(ExecuteError::NoDependFound)
+        ));
+    }
+}
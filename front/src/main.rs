@@ -1,119 +1,562 @@
+mod diagnostic;
 mod handle;
+mod progress;
+mod prompt;
+mod timing;
+mod trace;
 
+use api::config::{env_var, Config};
 use api::error::ExecuteError;
 use api::package::Package;
+use api::warnings::warn;
 use clap::{arg, command, Command};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-fn main() -> Result<(), ExecuteError> {
+// The exit status of --report-changes whenever something was changed.
+const CHANGED_STATUS: i32 = 2;
+
+// Whether the command changed something that --report-changes reports.
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+fn main() {
+    api::fetch::observe(progress::show);
+
+    let result = run();
+
+    // libraries that were installed before a failure still have to be found.
+    if let Err(e) = api::linker::refresh() {
+        warn(format!("couldn't refresh the linker cache: {}", e));
+    }
+
+    // like the warnings, the durations are shown whether the command succeeded or not.
+    timing::report();
+    handle::report_warnings();
+
+    if let Err(e) = result {
+        diagnostic::report(&e);
+        std::process::exit(1);
+    }
+
+    if CHANGED.load(Ordering::Relaxed) {
+        std::process::exit(CHANGED_STATUS);
+    }
+}
+
+fn run() -> Result<(), ExecuteError> {
     let command = command!()
         .arg_required_else_help(true)
         .propagate_version(true)
         .subcommand_required(true)
+        .arg(
+            arg!(--root <DIR> "Install packages relative to another root directory")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--"verbose-scripts" "Trace every command run by install scripts into the build log")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--"repo-precedence" <REPOS> "Comma separated repositories whose packages take precedence")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--"non-interactive" "Never prompt, and use the safe default answer of every prompt")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(-y --yes "Answer yes to every prompt")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(-v --verbose "Prints every step taken while resolving dependencies, and the output of install scripts")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--profile <NAME> "The build profile, see the profile.<NAME>.<VARIABLE> settings")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--"audit-scripts" "Warns about files install scripts touch outside of their build directory")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--"color-diagnostics" "Colors errors by their severity, even when not printing to a terminal")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--"fallback-binary" "Installs the prebuilt binary of a package whenever building it from source fails")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--enable <FEATURE> "Enables the dependencies of a feature, e.g. `x11: libxcb` lines within depends files")
+                .required(false)
+                .multiple_occurrences(true)
+                .global(true),
+        )
+        .arg(
+            arg!(--"no-ldconfig" "Doesn't refresh the linker cache after libraries were installed or removed")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--"max-rate" <KBPS> "Limits source downloads to KBPS kilobytes per second")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .global(true),
+        )
+        .arg(
+            arg!(--time "Prints how long building and installing every package took")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--"no-staleness-check" "Doesn't warn about repositories that haven't been updated in a while")
+                .required(false)
+                .global(true),
+        )
         .subcommand(
             Command::new("install")
                 .alias("i")
                 .about("Fetches & installs packages")
-                .arg(arg!([NAME]))
-                .arg(arg!(-i --install "Automatically install the packages, create symlinks etc")),
+                .arg(arg!([NAME] "The name of the package, or the path to a local package directory"))
+                .arg(arg!(-i --install "Automatically install the packages, create symlinks etc"))
+                .arg(arg!(--plan "Prints what would be built and installed, without doing anything"))
+                .arg(arg!(--"dry-run" "Prints what would be built, and the links that would be created, without touching the system"))
+                .arg(
+                    arg!(--format <FORMAT> "The output format of --plan")
+                        .required(false)
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                )
+                .arg(arg!(--force "Overwrites files that are in the way").conflicts_with("backup"))
+                .arg(arg!(--backup "Renames files that are in the way to <file>.pur-backup"))
+                .arg(arg!(--"force-rebuild-deps" "Rebuilds the packages and all of their dependencies from source"))
+                .arg(arg!(--"upgrade-deps" "Upgrades installed dependencies to their current version as well"))
+                .arg(arg!(-j --jobs <N> "Builds up to N packages at once, whenever they don't depend on each other").required(false))
+                .arg(arg!(-q --quiet "Doesn't print anything when every package is installed already"))
+                .arg(arg!(--"report-changes" "Exits with status 2 whenever something was built or installed")),
         )
         .subcommand(
             Command::new("build")
                 .alias("b")
                 .about("Builds packages without creating symlinks")
-                .arg(arg!([NAME])),
+                .arg(arg!([NAME] "The name of the package, or the path to a local package directory"))
+                .arg(arg!(--"output-dir" <DIR> "Places the built files within DIR instead of the install database").required(false)),
+        )
+        .subcommand(
+            Command::new("update")
+                .about("Updates the local repositories cached, and upgrades the installed packages")
+                .arg(arg!(--pretend "Only prints the installed packages that would be upgraded")),
+        )
+        .subcommand(
+            Command::new("outdated")
+                .about("Lists the installed packages with a newer version available, without upgrading them")
+                .arg(
+                    arg!(--format <FORMAT> "The output format")
+                        .required(false)
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                ),
+        )
+        .subcommand(
+            Command::new("versions")
+                .about("Lists every available version of a package, together with its repository")
+                .arg(arg!(<NAME> "The name of the package")),
+        )
+        .subcommand(
+            Command::new("checksum")
+                .about("Prints the checksums file of a package directory, downloading its sources")
+                .arg(arg!(<DIR> "The package directory")),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("Lists the installed packages, as recorded within the install database")
+                .arg(arg!(--built "Also lists the packages that are built, but not installed"))
+                .arg(
+                    arg!(--format <FORMAT> "The output format")
+                        .required(false)
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                )
+                .arg(arg!(--json "Short for --format json")),
         )
-        .subcommand(Command::new("update").about("Updates the local repositories cached"))
         .subcommand(
             Command::new("search")
                 .about("Search packages in local repositories.")
                 .arg(arg!(-i --installed "List all packages that are installed").required(false))
                 .arg(arg!(-n --name [NAME] "Filter packages starting with a string")),
         )
+        .subcommand(
+            Command::new("clean")
+                .about("Cleans up files pur keeps around")
+                .arg_required_else_help(true)
+                .arg(arg!(--logs "Deletes the build logs of every package"))
+                .arg(arg!(--build "Deletes the build directories of every package")),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Checks the install database for problems")
+                .arg(arg!(--fix "Fixes the problems that were found")),
+        )
+        .subcommand(
+            Command::new("config").about("Prints the effective configuration and its sources"),
+        )
+        .subcommand(
+            Command::new("version")
+                .about("Prints the versions of pur and its components, the config file and repositories in use"),
+        )
+        .subcommand(
+            Command::new("info")
+                .about("Prints information about packages, including how they were built and their dependency tree")
+                .arg(arg!([NAME]))
+                .arg(arg!(--rdeps "Also prints the installed packages depending on them")),
+        )
+        .subcommand(
+            Command::new("reindex")
+                .about("Reconstructs the manifest of packages installed before manifests were recorded")
+                .arg(arg!([NAME])),
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("Checks package definitions for common problems")
+                .arg(arg!([NAME])),
+        )
+        .subcommand(
+            Command::new("reinstall")
+                .about("Rebuilds & relinks installed packages at their current version")
+                .arg(arg!([NAME]... "The names of the installed packages")),
+        )
         .subcommand(
             Command::new("remove")
                 .about("Removes package binaries & from local database")
-                .arg(arg!([NAME])),
+                .arg(arg!([NAME]... "The names of the packages, which are all removed or none of them"))
+                .arg(arg!(--"ignore-not-installed" "Doesn't fail when a package isn't installed"))
+                .arg(arg!(--force "Removes packages even though installed packages still depend on them"))
+                .arg(arg!(--"dry-run" "Prints everything that would be deleted, without removing anything"))
+                .arg(arg!(-r --recursive "Also removes the dependencies no other installed package depends on, unless they're held")),
         );
 
     let matches = command.clone().get_matches();
 
+    // The settings are read by the api through their environment variables,
+    // so we just have to override these with the settings given on the command line.
+    for (key, value) in handle::cli_settings(&matches) {
+        set_setting(key, &value);
+    }
+
+    // Everything pur creates gets the same permissions, whatever umask it's invoked with.
+    api::config::apply_umask();
+
+    // This command doesn't have to do anything with the repositories,
+    // so we can handle it before fetching any packages.
+    if let Some(("config", _)) = matches.subcommand() {
+        handle::config(&matches);
+        return Ok(());
+    }
+
+    if let Some(("clean", matches)) = matches.subcommand() {
+        return handle::clean(matches);
+    }
+
+    if let Some(("checksum", matches)) = matches.subcommand() {
+        let dir = matches.get_one::<String>("DIR").map(String::as_str);
+
+        return handle::checksum(Path::new(dir.unwrap_or(".")));
+    }
+
+    if let Some(("doctor", matches)) = matches.subcommand() {
+        return handle::doctor(matches.is_present("fix"));
+    }
+
+    if let Some(("list", matches)) = matches.subcommand() {
+        let json = matches.is_present("json")
+            || matches.get_one::<String>("format").map(String::as_str) == Some("json");
+
+        handle::list(matches.is_present("built"), json);
+        return Ok(());
+    }
+
     // If we're here, it means the program has to do something with the repositories.
     // Therefore, we're free to fetch all repositories now.
-    let repositories = api::repo::get_repositories();
+    let mut repositories = api::repo::get_repositories();
+
+    // Packages are looked up in the order of the repositories, so reordering
+    // them is all we have to do to prefer one repository over another.
+    if let Some(precedence) = matches.get_one::<String>("repo-precedence") {
+        let precedence = precedence
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<&str>>();
+
+        repositories = api::repo::with_precedence(repositories, &precedence)?;
+    }
+
+    // This only reports the repositories, it doesn't need their packages.
+    if let Some(("version", _)) = matches.subcommand() {
+        handle::version(&repositories);
+        return Ok(());
+    }
 
     // We want to get all packages here, we could move this down later.
     // Currently, all commands require the packages to be fetched from the system,
     // and therefore it doesn't matter it's here.
     // We want some way to be able to detect if the command fetches packages later on,
     // because we don't want to have to refetch for every command.
-    let packages = repositories
-        .iter()
-        .flat_map(|repo| repo.get_packages())
-        .flatten()
-        .collect::<Vec<Package>>();
+    //
+    // A package that can't be parsed is left out, but the user should know why it's missing.
+    let mut packages = Vec::<Package>::new();
+
+    for (found, errors) in repositories.iter().flat_map(|repo| repo.scan()) {
+        packages.extend(found);
+
+        for (path, e) in errors {
+            warn(format!("skipping package {}: {}", path.display(), e));
+        }
+    }
+
+    // Only the commands changing what's installed are built from the package definitions,
+    // so those are the ones where outdated definitions matter.
+    match matches.subcommand() {
+        Some(("install", matches))
+            if !matches.is_present("plan")
+                && !matches.is_present("dry-run")
+                && !matches.is_present("quiet") =>
+        {
+            handle::check_staleness(&repositories)
+        }
+        Some(("build", _)) => handle::check_staleness(&repositories),
+        _ => {}
+    }
 
     match matches.subcommand() {
         Some(("install", matches)) => {
+            if matches.is_present("force") {
+                set_setting("on_conflict", "force");
+            } else if matches.is_present("backup") {
+                set_setting("on_conflict", "backup");
+            }
+
+            if matches.is_present("upgrade-deps") {
+                set_setting("upgrade_deps", "yes");
+            }
+
+            if let Some(jobs) = matches.get_one::<String>("jobs") {
+                set_setting("jobs", jobs);
+            }
+
             if let Some(to_install) = matches.get_many::<String>("NAME") {
-                let to_install = to_install
-                    .into_iter()
-                    .flat_map(|pkg| packages.iter().find(|x| &x.name == pkg)) // find a package which matches the name given by the user.
-                    .cloned()
-                    .collect::<Vec<Package>>();
-
-                // Install all packages.
-                // We should manually handle the error thrown by handle::install() here,
-                // but currently we're just panicing, so please do this in the future.
-                for package in to_install {
-                    handle::install(&package, &packages)?;
+                // names can also be paths to local package directories, see handle::find_package().
+                let to_install = handle::find_all(to_install, &packages, handle::find_package)?;
+
+                if matches.is_present("plan") {
+                    let json =
+                        matches.get_one::<String>("format").map(String::as_str) == Some("json");
+
+                    return handle::plan(&to_install, &packages, json);
+                }
+
+                if matches.is_present("dry-run") {
+                    return handle::install_dry_run(&to_install, &packages);
+                }
+
+                if matches.is_present("force-rebuild-deps") {
+                    return handle::rebuild_all(&to_install, &packages);
+                }
+
+                // Install all packages, the command fails with the first failure once
+                // everything that could be installed is.
+                let summary = handle::install(&to_install, &packages);
+
+                // provisioning tools run this over and over, so nothing changing is silent with --quiet.
+                if summary.changed() || !summary.failed.is_empty() || !matches.is_present("quiet") {
+                    handle::install_summary(&summary);
+                }
+
+                if summary.changed() && matches.is_present("report-changes") {
+                    CHANGED.store(true, Ordering::Relaxed);
+                }
+
+                if let Some((_, e)) = summary.failed.into_iter().next() {
+                    return Err(e);
                 }
             }
         }
         Some(("build", matches)) => {
             if let Some(to_build) = matches.get_many::<String>("NAME") {
-                let to_build = to_build
-                    .into_iter()
-                    .flat_map(|pkg| packages.iter().find(|x| &x.name == pkg)) // find a package which matches the name given by the user.
-                    .cloned()
-                    .collect::<Vec<Package>>();
+                // names can also be paths to local package directories, see handle::find_package().
+                let to_build = handle::find_all(to_build, &packages, handle::find_package)?;
+
+                match matches.get_one::<String>("output-dir") {
+                    Some(output) => {
+                        // the install script runs from within its build directory.
+                        let output = std::path::absolute(output).unwrap_or_else(|_| output.into());
 
-                for package in to_build {
-                    handle::build(&package, &packages)?;
+                        for package in to_build {
+                            handle::build_to(&package, &packages, &output)?;
+                        }
+                    }
+                    None => {
+                        for package in to_build {
+                            handle::build(&package, &packages)?;
+                        }
+                    }
                 }
             }
         }
-        Some(("update", _)) => {
+        Some(("update", matches)) => {
+            // The upgrades only start once every repository is up-to-date,
+            // so they're all built against the latest package definitions.
+            let mut queue = Vec::new();
+
             for repository in repositories {
-                match handle::update(&repository) {
-                    Ok(_) => {
-                        println!(
-                            "Updated {} repository",
-                            repository.dir.as_os_str().to_string_lossy()
-                        )
-                    }
+                // remote repositories are known by their URL rather than by their mirror.
+                let location = match &repository.url {
+                    Some(url) => url.clone(),
+                    None => repository.dir.as_os_str().to_string_lossy().into_owned(),
+                };
+
+                match handle::update(&repository, &mut queue) {
+                    Ok(_) => println!("Updated {} repository", location),
                     Err(e) => {
-                        println!(
-                            "Failed to update {:?} repository, {:?}",
-                            repository.dir.as_os_str().to_string_lossy(),
-                            e
-                        )
+                        println!("Failed to update {} repository", location);
+                        diagnostic::report(&e);
                     }
                 };
             }
+
+            handle::upgrade(&queue, matches.is_present("pretend"));
+        }
+        Some(("reinstall", matches)) => {
+            if let Some(to_reinstall) = matches.get_many::<String>("NAME") {
+                // a name can also be an alias, see handle::find_named().
+                let to_reinstall = handle::find_all(to_reinstall, &packages, handle::find_named)?;
+                let to_reinstall = handle::check_installed(to_reinstall, false)?;
+
+                handle::reinstall(&to_reinstall)?;
+            }
         }
         Some(("remove", matches)) => {
             if let Some(to_remove) = matches.get_many::<String>("NAME") {
-                let to_remove = to_remove
-                    .into_iter()
-                    .flat_map(|pkg| packages.iter().find(|x| &x.name == pkg)) // find a package which matches the name given by the user.
-                    .cloned()
-                    .collect::<Vec<Package>>();
-
-                // Install all packages.
-                // We should manually handle the error thrown by handle::install() here,
-                // but currently we're just panicing, so please do this in the future.
+                // a name can also be an alias, see handle::find_named(). Names that don't match
+                // any package aren't installed either, which --ignore-not-installed is fine with.
+                let to_remove = match handle::find_all(to_remove, &packages, handle::find_named) {
+                    Err(ExecuteError::PackageNotFound(_))
+                        if matches.is_present("ignore-not-installed") =>
+                    {
+                        matches
+                            .get_many::<String>("NAME")
+                            .into_iter()
+                            .flatten()
+                            .flat_map(|pkg| handle::find_named(pkg, &packages))
+                            .collect()
+                    }
+                    to_remove => to_remove?,
+                };
+
+                // every package is checked before anything is removed, so a failing check
+                // doesn't leave the removal half done.
+                let to_remove =
+                    handle::check_installed(to_remove, matches.is_present("ignore-not-installed"))?;
+
+                // the whole set is computed and shown up front, so nothing is removed
+                // before the user knows about every orphan that goes with it.
+                let to_remove = match matches.is_present("recursive") {
+                    true => {
+                        let removal = handle::with_orphans(to_remove, &packages);
+                        handle::removal_summary(&removal);
+                        removal.packages
+                    }
+                    false => to_remove,
+                };
+
+                // the dry run lists the packages depending on them instead of failing on them.
+                if matches.is_present("dry-run") {
+                    for package in to_remove {
+                        handle::remove_dry_run(&package, &packages)?;
+                    }
+
+                    return Ok(());
+                }
+
+                // nothing is removed while an installed package still needs it, unless forced.
+                if !matches.is_present("force") {
+                    handle::check_dependents(&to_remove, &packages)?;
+                }
+
+                let names = to_remove
+                    .iter()
+                    .map(|package| format!("{} v{}", package.name, package.version))
+                    .collect::<Vec<String>>();
+
+                // Removing is destructive, so we ask first whenever there's someone to ask.
+                if !names.is_empty()
+                    && !prompt::confirm(&format!("Remove {}?", names.join(", ")), true)
+                {
+                    println!("Aborting removal.");
+                    return Err(ExecuteError::Aborted);
+                }
+
+                // only removing itself can still fail from here on, e.g. on a read-only file system.
                 for package in to_remove {
-                    handle::remove(&package)?;
+                    handle::remove(&package, matches.is_present("ignore-not-installed"))?;
+                }
+            }
+        }
+        Some(("outdated", matches)) => {
+            let json = matches.get_one::<String>("format").map(String::as_str) == Some("json");
+
+            handle::outdated(&packages, json);
+        }
+        Some(("versions", matches)) => {
+            if let Some(name) = matches.get_one::<String>("NAME") {
+                handle::versions(name, &packages);
+            }
+        }
+        Some(("info", matches)) => {
+            if let Some(names) = matches.get_many::<String>("NAME") {
+                // names can also be paths to local package directories, see handle::find_package().
+                for package in handle::find_all(names, &packages, handle::find_package)? {
+                    handle::info(&package, &packages);
+
+                    if matches.is_present("rdeps") {
+                        handle::rdeps(&package, &packages);
+                    }
+                }
+            }
+        }
+        Some(("reindex", matches)) => {
+            if let Some(names) = matches.get_many::<String>("NAME") {
+                for package in handle::find_all(names, &packages, handle::find_named)? {
+                    handle::reindex(&package)?;
+                }
+            }
+        }
+        Some(("lint", matches)) => {
+            if let Some(to_lint) = matches.get_many::<String>("NAME") {
+                // a name can also be an alias, see handle::find_named().
+                let to_lint = handle::find_all(to_lint, &packages, handle::find_named)?;
+
+                // We want to lint every package before failing, so all problems are shown at once.
+                let mut valid = true;
+
+                for package in to_lint {
+                    valid &= handle::lint(&package, &packages);
+                }
+
+                if !valid {
+                    return Err(ExecuteError::InvalidPackage);
                 }
             }
         }
@@ -129,22 +572,33 @@ fn main() -> Result<(), ExecuteError> {
                         return package.name.starts_with(value);
                     }
 
-                    return true;
+                    true
                 })
                 .collect::<Vec<&Package>>();
 
             for package in packages {
                 let mut str = format!("{} v{}", package.name, package.version);
 
-                if package.is_installed().is_some() {
-                    str += " [installed]";
+                match package.is_installed().map(|data| data.installed_at) {
+                    Some(Some(time)) => {
+                        str += &format!(" [installed {}]", handle::format_time(time))
+                    }
+                    Some(None) => str += " [installed]",
+                    None => {}
                 }
 
                 println!("{}", str)
             }
         }
-        _ => unreachable!("Exhausted list of sub commands"),
+        // every subcommand is handled above, clap doesn't let anything else through.
+        _ => command.clone().print_help()?,
     };
 
     Ok(())
 }
+
+// Overrides a setting for the rest of the command, which the api reads through its environment variable.
+fn set_setting(key: &str, value: &str) {
+    std::env::set_var(env_var(key), value);
+    Config::invalidate();
+}
@@ -0,0 +1,70 @@
+use api::fetch::Progress;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// The width of the progress bar, in characters.
+const BAR_WIDTH: u64 = 30;
+
+// The frames of the spinner shown for downloads of an unknown size.
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+// How often the spinner was drawn, which picks its next frame.
+static SPINS: AtomicUsize = AtomicUsize::new(0);
+
+/// Shows the progress of a download on the current line, as a progress bar whenever the server
+/// sent the size of the file and as a spinner otherwise. See [api::fetch::observe].
+///
+/// Nothing is shown when the output isn't a terminal, e.g. within logs.
+pub fn show(url: &str, progress: Progress) {
+    if !io::stdout().is_terminal() {
+        return;
+    }
+
+    let file = url.rsplit('/').next().unwrap_or(url);
+    let status = match progress.total {
+        Some(total) if total > 0 => {
+            let filled = (progress.downloaded.min(total) * BAR_WIDTH / total) as usize;
+
+            format!(
+                "[{:<width$}] {} of {}",
+                "#".repeat(filled),
+                size(progress.downloaded),
+                size(total),
+                width = BAR_WIDTH as usize
+            )
+        }
+        _ if progress.done => size(progress.downloaded),
+        _ => format!(
+            "{} {}",
+            SPINNER[SPINS.fetch_add(1, Ordering::Relaxed) % SPINNER.len()],
+            size(progress.downloaded)
+        ),
+    };
+
+    // the line is cleared first, as the status may have gotten shorter.
+    let mut stdout = io::stdout().lock();
+    let _ = write!(stdout, "\r\x1b[2KDownloading {}: {}", file, status);
+
+    if progress.done {
+        let _ = writeln!(stdout);
+    }
+
+    let _ = stdout.flush();
+}
+
+// A number of bytes in the largest unit that keeps it at 1 or above.
+fn size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, units[unit]),
+    }
+}
@@ -0,0 +1,39 @@
+use api::config::Config;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Asks the user a yes/no question, and returns the answer.
+///
+/// Every prompt has a default, which is the answer used whenever pur runs non-interactively
+/// (through --non-interactive or `PUR_NONINTERACTIVE`), or when stdin isn't a terminal, so pur never
+/// blocks waiting for input. Passing --yes (or `PUR_ASSUME_YES`) answers every prompt with yes instead.
+///
+/// The defaults of the current prompts are:
+/// - removing packages: go ahead, as removing never asked before, so scripts keep working
+pub fn confirm(question: &str, default: bool) -> bool {
+    let config = Config::load();
+
+    if config.flag("assume_yes") {
+        return true;
+    }
+
+    if config.flag("noninteractive") || !io::stdin().is_terminal() {
+        return default;
+    }
+
+    let options = if default { "[Y/n]" } else { "[y/N]" };
+
+    print!("{} {} ", question, options);
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return default;
+    }
+
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => true,
+        "n" | "no" => false,
+        _ => default,
+    }
+}
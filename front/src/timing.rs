@@ -0,0 +1,92 @@
+use api::config::Config;
+use api::package::Package;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+// How long building and installing each package took, in the order they were built.
+static TIMINGS: Mutex<Vec<Timing>> = Mutex::new(Vec::new());
+
+struct Timing {
+    package: String,
+    build: Option<Duration>,
+    install: Option<Duration>,
+}
+
+/// Builds the package through `step`, and records how long that took.
+pub fn build<T>(package: &Package, step: impl FnOnce() -> T) -> T {
+    measure(package, step, |timing, took| timing.build = Some(took))
+}
+
+/// Installs the package through `step`, and records how long that took.
+pub fn install<T>(package: &Package, step: impl FnOnce() -> T) -> T {
+    measure(package, step, |timing, took| timing.install = Some(took))
+}
+
+fn measure<T>(
+    package: &Package,
+    step: impl FnOnce() -> T,
+    record: impl FnOnce(&mut Timing, Duration),
+) -> T {
+    let started = Instant::now();
+    let result = step();
+    let label = format!("{} v{}", package.name, package.version);
+
+    let mut timings = TIMINGS.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let index = match timings.iter().position(|timing| timing.package == label) {
+        Some(index) => index,
+        None => {
+            timings.push(Timing {
+                package: label,
+                build: None,
+                install: None,
+            });
+            timings.len() - 1
+        }
+    };
+
+    record(&mut timings[index], started.elapsed());
+    result
+}
+
+/// Prints how long building and installing every package took with --time (the `time` setting),
+/// as a table in build order followed by the totals.
+pub fn report() {
+    let timings = std::mem::take(&mut *TIMINGS.lock().unwrap_or_else(PoisonError::into_inner));
+
+    if timings.is_empty() || !Config::load().flag("time") {
+        return;
+    }
+
+    let total = |step: fn(&Timing) -> Option<Duration>| -> Option<Duration> {
+        timings.iter().flat_map(step).reduce(|a, b| a + b)
+    };
+    let total = Timing {
+        package: "total".to_owned(),
+        build: total(|timing| timing.build),
+        install: total(|timing| timing.install),
+    };
+
+    let width = timings
+        .iter()
+        .map(|timing| timing.package.len())
+        .max()
+        .unwrap_or_default()
+        .max("package".len())
+        .max(total.package.len());
+    let format = |took: Option<Duration>| match took {
+        Some(took) => format!("{:.2}s", took.as_secs_f64()),
+        None => "-".to_owned(),
+    };
+
+    println!("{:width$}  {:>10}  {:>10}", "package", "build", "install");
+
+    for timing in timings.iter().chain([&total]) {
+        println!(
+            "{:width$}  {:>10}  {:>10}",
+            timing.package,
+            format(timing.build),
+            format(timing.install)
+        );
+    }
+}
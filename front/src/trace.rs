@@ -0,0 +1,55 @@
+use api::config::Config;
+use api::package::Package;
+
+/// The path taken while resolving the dependencies of a package, e.g.
+/// `installing foo → requires bar → found bar 1.2 in /usr/repo/pur → requires baz → NOT FOUND`.
+///
+/// The whole path is printed whenever resolution fails, and every step is printed
+/// as it's taken with --verbose.
+pub struct Trace {
+    steps: Vec<String>,
+    verbose: bool,
+}
+
+impl Trace {
+    pub fn new(action: &str, package: &Package) -> Self {
+        let mut trace = Self {
+            steps: Vec::new(),
+            verbose: Config::load().flag("verbose"),
+        };
+
+        trace.push(format!("{} {}", action, package.name));
+        trace
+    }
+
+    pub fn push(&mut self, step: String) {
+        if self.verbose {
+            println!("{}{}", "  ".repeat(self.steps.len()), step);
+        }
+
+        self.steps.push(step);
+    }
+
+    pub fn pop(&mut self) {
+        self.steps.pop();
+    }
+
+    /// Records that a dependency has been found, together with the repository it's in.
+    pub fn found(&mut self, package: &Package) {
+        let repo = package
+            .dir()
+            .parent()
+            .map(|repo| repo.display().to_string())
+            .unwrap_or_default();
+
+        self.push(format!(
+            "found {} {} in {}",
+            package.name, package.version, repo
+        ));
+    }
+
+    /// Prints the path that was taken, ending with the step that failed.
+    pub fn fail(&self, step: &str) {
+        println!("{} → {}", self.steps.join(" → "), step);
+    }
+}